@@ -0,0 +1,224 @@
+//! Encrypted wallet backups
+//!
+//! A backup bundles the keys, wallet metadata, notes and history into one file,
+//! sealed under its own password. It works with either store, and restoring one
+//! merges it into an existing wallet instead of replacing it.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+use crate::crypto;
+use crate::error::{ClientError, Result};
+use crate::history::HistoryEntry;
+use crate::state::{Commitment, Keyring};
+use crate::store;
+use crate::vault::{KdfParams, WalletCipher};
+
+/// Marks a file as a backup bundle
+const BACKUP_FORMAT: &str = "private-token-backup";
+
+/// Current version of the backup format
+const BACKUP_VERSION: u32 = 1;
+
+/// Associated data binding the ciphertext to the backup format
+const BACKUP_AAD: &[u8] = b"private-token backup v1";
+
+/// Everything needed to rebuild a wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    /// When the backup was made, in seconds since the Unix epoch
+    pub created_at: u64,
+    /// Keys and wallet metadata
+    pub keyring: Keyring,
+    /// Every note, in any state
+    pub commitments: Vec<Commitment>,
+    /// Every history entry
+    pub history: Vec<HistoryEntry>,
+}
+
+impl Backup {
+    /// Check that the contents are consistent before anything is merged
+    ///
+    /// Every account key must derive its address, and every note must open to its
+    /// commitment.
+    pub fn verify(&self) -> Result<()> {
+        self.keyring.check_hash_version()?;
+        for (address, secret) in &self.keyring.accounts {
+            if secret.address() != *address {
+                return Err(ClientError::InvalidBackup(format!(
+                    "key for {} derives a different address",
+                    address
+                )));
+            }
+        }
+        for note in &self.commitments {
+            let expected = crypto::compute_commitment(
+                note.scheme,
+                &note.address,
+                note.balance,
+                note.nonce,
+                &note.blinding,
+            );
+            if expected != note.commitment {
+                return Err(ClientError::InvalidBackup(format!(
+                    "note {} does not open to its commitment",
+                    note.commitment
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What merging a backup into a wallet changed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RestoreReport {
+    /// Accounts and view-only accounts added
+    pub accounts_added: usize,
+    /// Notes added
+    pub notes_added: usize,
+    /// Notes the backup shows as spent that the wallet still had as spendable
+    pub notes_spent: usize,
+    /// Notes the wallet already had in a different state, which were left as they are
+    pub notes_kept: usize,
+    /// History entries added
+    pub history_added: usize,
+    /// Labels not restored because the wallet uses them for another account, or
+    /// already labels the account differently
+    pub labels_skipped: Vec<String>,
+}
+
+/// The on-disk form of a backup
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupFile {
+    /// Always [`BACKUP_FORMAT`]
+    format: String,
+    /// Format version
+    version: u32,
+    /// Password KDF parameters
+    kdf: KdfParams,
+    /// Nonce followed by the encrypted [`Backup`] and its Poly1305 tag
+    #[serde(with = "hex")]
+    data: Vec<u8>,
+}
+
+/// Encrypt a backup under `password` and write it to `path`
+///
+/// Refuses to overwrite an existing file.
+pub fn write(path: &str, backup: &Backup, password: &str) -> Result<()> {
+    if Path::new(path).exists() {
+        return Err(ClientError::InvalidInput(format!("{} already exists", path)));
+    }
+    let cipher = WalletCipher::new(password)?;
+    let plaintext = Zeroizing::new(serde_json::to_vec(backup)?);
+    let file = BackupFile {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        kdf: cipher.kdf().clone(),
+        data: cipher.encrypt(BACKUP_AAD, &plaintext)?,
+    };
+    store::write_atomic(path, serde_json::to_string_pretty(&file)?.as_bytes())
+}
+
+/// Read, decrypt and verify the backup at `path`
+///
+/// A wrong password and a modified file both fail authentication.
+pub fn read(path: &str, password: &str) -> Result<Backup> {
+    let data = fs::read_to_string(path)?;
+    let file: BackupFile = serde_json::from_str(&data)
+        .map_err(|e| ClientError::InvalidBackup(format!("{} is not a backup: {}", path, e)))?;
+    if file.format != BACKUP_FORMAT {
+        return Err(ClientError::InvalidBackup(format!("{} is not a backup", path)));
+    }
+    if file.version != BACKUP_VERSION {
+        return Err(ClientError::InvalidBackup(format!(
+            "unsupported backup version {}",
+            file.version
+        )));
+    }
+
+    let cipher = WalletCipher::derive(file.kdf, password)?;
+    let plaintext = cipher.decrypt(BACKUP_AAD, &file.data)?;
+    let backup: Backup = serde_json::from_slice(&plaintext)?;
+    backup.verify()?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::HashScheme;
+    use crate::field::Field;
+    use crate::keys::SpendingKey;
+
+    const TEST_PASSWORD: &str = "backup password";
+
+    fn backup() -> Backup {
+        let secret = SpendingKey::generate();
+        let address = secret.address();
+        let blinding = crypto::generate_blinding();
+        let mut keyring = Keyring::new();
+        keyring.accounts.insert(address, secret.clone());
+        Backup {
+            created_at: 0,
+            keyring,
+            commitments: vec![Commitment {
+                commitment: crypto::compute_commitment(
+                    HashScheme::Pedersen,
+                    &address,
+                    40,
+                    1,
+                    &blinding,
+                ),
+                blinding,
+                secret: Some(secret),
+                block_number: Some(3),
+                ..Commitment::test_note(1, address, 40)
+            }],
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.backup");
+        let path = path.to_str().unwrap();
+        let original = backup();
+
+        write(path, &original, TEST_PASSWORD).unwrap();
+        assert!(write(path, &original, TEST_PASSWORD).is_err());
+
+        let restored = read(path, TEST_PASSWORD).unwrap();
+        assert_eq!(restored.keyring.accounts, original.keyring.accounts);
+        assert_eq!(restored.commitments[0].commitment, original.commitments[0].commitment);
+        assert!(matches!(
+            read(path, "wrong password"),
+            Err(ClientError::InvalidPassword)
+        ));
+
+        // Flipping a ciphertext bit fails authentication
+        let mut file: BackupFile =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        let last = file.data.len() - 1;
+        file.data[last] ^= 1;
+        fs::write(path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(read(path, TEST_PASSWORD).is_err());
+    }
+
+    #[test]
+    fn test_verify_rejects_inconsistent_contents() {
+        assert!(backup().verify().is_ok());
+
+        let mut tampered = backup();
+        tampered.commitments[0].balance = 4_000;
+        assert!(matches!(tampered.verify(), Err(ClientError::InvalidBackup(_))));
+
+        let mut tampered = backup();
+        let other = SpendingKey::generate();
+        tampered.keyring.accounts.insert(Field::from(1u64), other);
+        assert!(matches!(tampered.verify(), Err(ClientError::InvalidBackup(_))));
+    }
+}
@@ -0,0 +1,320 @@
+//! Barretenberg proving through the `bb` command-line tool
+//!
+//! Each call writes its inputs to a temporary directory, runs `bb` on them and reads
+//! back what it wrote. Proofs and keys use the EVM target (a Keccak transcript), so
+//! they match the Solidity verifiers `bb` generates.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::OnceLock;
+
+use acvm::acir::native_types::WitnessStack;
+use acvm::FieldElement;
+
+use crate::error::{ClientError, Result};
+use crate::field::Field;
+use crate::prover::{Circuit, Proof, ProverBackend, VerificationKey};
+
+/// Environment variable that overrides the `bb` binary to run
+pub const BB_BINARY_ENV: &str = "BB_BINARY";
+
+/// Major version of `bb` the circuits and verifiers are built with
+///
+/// Proofs from a different major version don't verify against the deployed
+/// verifiers.
+pub const BB_MAJOR_VERSION: u64 = 3;
+
+/// Proves with a locally installed `bb` binary
+#[derive(Debug)]
+pub struct BbCli {
+    binary: PathBuf,
+    /// Outcome of the version check, made on first use
+    version: OnceLock<std::result::Result<String, String>>,
+}
+
+impl BbCli {
+    /// Run the given `bb` binary
+    pub fn new(binary: impl Into<PathBuf>) -> Self {
+        Self {
+            binary: binary.into(),
+            version: OnceLock::new(),
+        }
+    }
+
+    /// Run `$BB_BINARY`, or `bb` from `PATH`
+    pub fn from_env() -> Self {
+        Self::new(std::env::var_os(BB_BINARY_ENV).unwrap_or_else(|| "bb".into()))
+    }
+
+    /// The installed version, after checking it is the one the verifiers expect
+    pub fn version(&self) -> Result<String> {
+        self.version
+            .get_or_init(|| {
+                let output = self.spawn(&["--version"])?;
+                let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                match major_version(&version) {
+                    Some(BB_MAJOR_VERSION) => Ok(version),
+                    _ => Err(format!(
+                        "bb {} is installed, but proofs need bb {}.x",
+                        version, BB_MAJOR_VERSION
+                    )),
+                }
+            })
+            .clone()
+            .map_err(ClientError::ProofError)
+    }
+
+    /// Start `bb` with `args` and wait for it
+    fn spawn(&self, args: &[&str]) -> std::result::Result<Output, String> {
+        Command::new(&self.binary).args(args).output().map_err(|e| {
+            if e.kind() == ErrorKind::NotFound {
+                format!(
+                    "{} not found: install Barretenberg {}.x with bbup, or set {}",
+                    self.binary.display(),
+                    BB_MAJOR_VERSION,
+                    BB_BINARY_ENV
+                )
+            } else {
+                format!("Failed to run {}: {}", self.binary.display(), e)
+            }
+        })
+    }
+
+    /// Run a `bb` command, failing if it exits unsuccessfully
+    fn run(&self, args: &[&str]) -> Result<Output> {
+        self.version()?;
+        let output = self.spawn(args).map_err(ClientError::ProofError)?;
+        if !output.status.success() {
+            return Err(command_failed(args[0], &output));
+        }
+        Ok(output)
+    }
+}
+
+/// The error for a `bb` command that didn't complete
+fn command_failed(command: &str, output: &Output) -> ClientError {
+    ClientError::ProofError(format!(
+        "bb {} failed ({}): {}",
+        command,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+impl ProverBackend for BbCli {
+    fn prove(&self, circuit: &Circuit, witness: &WitnessStack<FieldElement>) -> Result<Proof> {
+        let dir = tempfile::tempdir()?;
+        let witness_path = dir.path().join("witness.gz");
+        let serialized = witness
+            .serialize()
+            .map_err(|e| ClientError::ProofError(format!("Failed to serialize witness: {}", e)))?;
+        fs::write(&witness_path, serialized)?;
+
+        self.run(&[
+            "prove",
+            "-b",
+            circuit.path(),
+            "-w",
+            path_str(&witness_path)?,
+            "-o",
+            path_str(dir.path())?,
+            "-t",
+            "evm",
+        ])?;
+
+        Ok(Proof {
+            proof: fs::read(dir.path().join("proof"))?,
+            public_inputs: parse_public_inputs(&fs::read(dir.path().join("public_inputs"))?)?,
+        })
+    }
+
+    fn verify(&self, vk: &[u8], proof: &Proof) -> Result<bool> {
+        let dir = tempfile::tempdir()?;
+        let vk_path = dir.path().join("vk");
+        let proof_path = dir.path().join("proof");
+        let inputs_path = dir.path().join("public_inputs");
+        fs::write(&vk_path, vk)?;
+        fs::write(&proof_path, &proof.proof)?;
+        let inputs: Vec<u8> = proof.public_inputs.iter().flat_map(Field::to_bytes).collect();
+        fs::write(&inputs_path, inputs)?;
+
+        self.version()?;
+        let args = [
+            "verify",
+            "-k",
+            path_str(&vk_path)?,
+            "-p",
+            path_str(&proof_path)?,
+            "-i",
+            path_str(&inputs_path)?,
+            "-t",
+            "evm",
+        ];
+        let output = self.spawn(&args).map_err(ClientError::ProofError)?;
+        // `bb verify` exits with 1 and prints nothing for a proof that doesn't verify;
+        // a key or proof it can't read is reported on stderr
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(1) if output.stderr.trim_ascii().is_empty() => Ok(false),
+            _ => Err(command_failed("verify", &output)),
+        }
+    }
+
+    fn write_vk(&self, circuit: &Circuit) -> Result<VerificationKey> {
+        let dir = tempfile::tempdir()?;
+        self.run(&[
+            "write_vk",
+            "-b",
+            circuit.path(),
+            "-o",
+            path_str(dir.path())?,
+            "-t",
+            "evm",
+        ])?;
+        Ok(VerificationKey {
+            key: fs::read(dir.path().join("vk"))?,
+            hash: parse_vk_hash(&fs::read(dir.path().join("vk_hash"))?)?,
+        })
+    }
+
+    fn version(&self) -> Result<String> {
+        BbCli::version(self)
+    }
+}
+
+/// The major version in `bb --version` output, e.g. `3` for `v3.0.0-nightly.20251104`
+fn major_version(version: &str) -> Option<u64> {
+    version.trim().trim_start_matches('v').split('.').next()?.parse().ok()
+}
+
+/// Split `bb`'s public inputs file into 32-byte big-endian field elements
+fn parse_public_inputs(bytes: &[u8]) -> Result<Vec<Field>> {
+    if !bytes.len().is_multiple_of(32) {
+        return Err(ClientError::ProofError(format!(
+            "bb wrote {} bytes of public inputs, not a multiple of 32",
+            bytes.len()
+        )));
+    }
+    bytes
+        .chunks(32)
+        .map(|chunk| Field::from_bytes(chunk.try_into().expect("chunks are 32 bytes")))
+        .collect()
+}
+
+/// Read `bb`'s `vk_hash` file: 32 raw bytes, or hex text
+fn parse_vk_hash(bytes: &[u8]) -> Result<[u8; 32]> {
+    if let Ok(hash) = bytes.try_into() {
+        return Ok(hash);
+    }
+    let text = String::from_utf8_lossy(bytes);
+    let mut hash = [0u8; 32];
+    hex::decode_to_slice(text.trim().trim_start_matches("0x"), &mut hash)
+        .map_err(|e| ClientError::ProofError(format!("bb wrote an unreadable vk_hash: {}", e)))?;
+    Ok(hash)
+}
+
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| {
+        ClientError::ProofError(format!("Path is not valid UTF-8: {}", path.display()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_major_version() {
+        assert_eq!(major_version("3.0.0"), Some(3));
+        assert_eq!(major_version("v3.0.0-nightly.20251104\n"), Some(3));
+        assert_eq!(major_version("0.82.2"), Some(0));
+        assert_eq!(major_version("bb"), None);
+    }
+
+    #[test]
+    fn test_parse_public_inputs() {
+        let mut bytes = Field::from(7u64).to_bytes().to_vec();
+        bytes.extend(Field::from(9u64).to_bytes());
+        assert_eq!(
+            parse_public_inputs(&bytes).unwrap(),
+            vec![Field::from(7u64), Field::from(9u64)]
+        );
+        assert!(parse_public_inputs(&bytes[..40]).is_err());
+    }
+
+    #[test]
+    fn test_parse_vk_hash() {
+        let hash = [0xab; 32];
+        assert_eq!(parse_vk_hash(&hash).unwrap(), hash);
+        let text = format!("0x{}\n", hex::encode(hash));
+        assert_eq!(parse_vk_hash(text.as_bytes()).unwrap(), hash);
+        assert!(parse_vk_hash(b"0x1234").is_err());
+    }
+
+    #[test]
+    fn test_missing_binary() {
+        let bb = BbCli::new("/nonexistent/bb");
+        let err = bb.version().unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+
+    /// Shell commands of a `bb` that fails
+    #[cfg(unix)]
+    const FAILING: &str = "echo 'circuit too large' >&2\nexit 3";
+
+    /// A stand-in `bb` that reports `version` and runs `commands` for every other command
+    #[cfg(unix)]
+    fn fake_bb(dir: &Path, version: &str, commands: &str) -> BbCli {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join("bb");
+        let script = format!(
+            "#!/bin/sh\nif [ \"$1\" = --version ]; then echo {}; exit 0; fi\n{}\n",
+            version, commands
+        );
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        BbCli::new(path)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_version_mismatch_and_failed_command() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let old = fake_bb(dir.path(), "0.82.2", FAILING);
+        let err = old.run(&["write_vk"]).unwrap_err();
+        assert!(err.to_string().contains("bb 0.82.2 is installed"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let current = fake_bb(dir.path(), "3.0.0", FAILING);
+        assert_eq!(current.version().unwrap(), "3.0.0");
+        let err = current.run(&["write_vk"]).unwrap_err().to_string();
+        assert!(err.contains("bb write_vk failed"));
+        assert!(err.contains("circuit too large"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_verify_tells_rejected_proofs_from_errors() {
+        let proof = Proof {
+            proof: vec![1; 64],
+            public_inputs: vec![Field::from(7u64)],
+        };
+        let verify = |commands: &str| {
+            let dir = tempfile::tempdir().unwrap();
+            fake_bb(dir.path(), "3.0.0", commands).verify(b"vk", &proof)
+        };
+
+        assert!(verify("exit 0").unwrap());
+        assert!(!verify("exit 1").unwrap());
+
+        let err = verify("echo 'Unable to read vk' >&2\nexit 1").unwrap_err().to_string();
+        assert!(err.contains("bb verify failed"));
+        assert!(err.contains("Unable to read vk"));
+        let err = verify(FAILING).unwrap_err().to_string();
+        assert!(err.contains("circuit too large"));
+    }
+}
@@ -1,29 +1,40 @@
 //! Ethereum contract interaction
 
-use alloy_primitives::{Address, Bytes, FixedBytes, U256};
-use alloy_sol_types::sol;
+use alloy::providers::{Provider, ProviderBuilder, RootProvider};
+use alloy::rpc::types::eth::{BlockNumberOrTag, Filter, Log, TransactionRequest};
+use alloy::transports::http::{Client, Http};
+use alloy_primitives::{Address, Bytes, FixedBytes, B256};
+use alloy_sol_types::{sol, SolCall, SolEvent};
+use std::collections::HashSet;
 use std::str::FromStr;
 
 use crate::error::{ClientError, Result};
-use crate::prover::Proof;
+use crate::field::Field;
+use crate::note::{self, EncryptedNote};
+use crate::prover::{CircuitKind, Proof, ProofGenerator};
 
 // Define the contract interface using alloy's sol! macro
 sol! {
     #[derive(Debug)]
     interface IPrivateToken {
         function mint(bytes calldata proof, bytes32[] calldata publicInputs) external;
-        function transfer(bytes calldata proof, bytes32[] calldata publicInputs) external;
+        function transfer(bytes calldata proof, bytes32[] calldata publicInputs, bytes calldata encryptedNote) external;
         function hasCommitment(bytes32 commitment) external view returns (bool);
         function isNullifierUsed(bytes32 nullifier) external view returns (bool);
         function getCommitmentCount() external view returns (uint256);
+        function transferVerifier() external view returns (address);
+        function mintVerifier() external view returns (address);
         
         event CommitmentAdded(bytes32 indexed commitment, uint256 indexed index);
         event NullifierUsed(bytes32 indexed nullifier);
-        event PrivateTransfer(bytes32 indexed nullifier, bytes32 senderOutput, bytes32 recipientOutput, uint256 timestamp);
+        event PrivateTransfer(bytes32 indexed nullifier, bytes32 senderOutput, bytes32 recipientOutput, uint256 timestamp, bytes encryptedNote);
         event PrivateMint(bytes32 indexed commitment, uint256 requestId, uint256 timestamp);
     }
 }
 
+/// JSON-RPC provider over HTTP
+type HttpProvider = RootProvider<Http<Client>>;
+
 /// Configuration for the contract client
 #[derive(Debug, Clone)]
 pub struct ContractConfig {
@@ -48,15 +59,45 @@ impl ContractConfig {
     }
 }
 
+/// Outcome of a submitted transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxStatus {
+    /// Not mined yet
+    Pending,
+    /// Mined and succeeded
+    Confirmed { block_number: u64 },
+    /// Reverted
+    Failed,
+    /// Neither mined nor known to the node
+    ///
+    /// The transaction may have been dropped from the mempool, or may not have
+    /// reached this node yet.
+    Unknown,
+}
+
 /// Client for interacting with the PrivateToken contract
 pub struct PrivateTokenContract {
     config: ContractConfig,
+    /// Checks proofs before they are submitted
+    verifier: Option<ProofGenerator>,
 }
 
 impl PrivateTokenContract {
     /// Create a new contract client
+    ///
+    /// It can read from the contract straight away, but needs a verifier (see
+    /// [`Self::with_verifier`]) before it submits proofs.
     pub fn new(config: ContractConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            verifier: None,
+        }
+    }
+
+    /// Verify proofs locally with `verifier` before submitting them
+    pub fn with_verifier(mut self, verifier: ProofGenerator) -> Self {
+        self.verifier = Some(verifier);
+        self
     }
 
     /// Create from environment variables
@@ -65,8 +106,32 @@ impl PrivateTokenContract {
         Ok(Self::new(config))
     }
 
+    /// Provider for the configured RPC endpoint
+    fn provider(&self) -> Result<HttpProvider> {
+        let url = self.config.rpc_url.parse().map_err(|e| {
+            ClientError::InvalidInput(format!("Invalid RPC URL {}: {}", self.config.rpc_url, e))
+        })?;
+        Ok(ProviderBuilder::new().on_http(url))
+    }
+
+    /// Address of the PrivateToken contract
+    fn contract_address(&self) -> Result<Address> {
+        Address::from_str(&self.config.contract_address).map_err(|e| {
+            ClientError::InvalidInput(format!(
+                "Invalid contract address {}: {}",
+                self.config.contract_address, e
+            ))
+        })
+    }
+
     /// Mint tokens privately
+    ///
+    /// The proof is verified locally first, and the on-chain verifier is checked to
+    /// hold the same verification key, so a proof it would reject is never
+    /// submitted.
     pub async fn mint(&self, proof: Proof) -> Result<String> {
+        self.check_proof(&proof, CircuitKind::Mint)?;
+        self.check_verifier(CircuitKind::Mint).await?;
         tracing::info!("Submitting mint transaction...");
         
         // Convert proof to contract format
@@ -74,7 +139,7 @@ impl PrivateTokenContract {
         let public_inputs: Vec<FixedBytes<32>> = proof
             .public_inputs
             .iter()
-            .map(|p| FixedBytes::from_slice(p))
+            .map(|p| FixedBytes::from(*p))
             .collect();
 
         // Build and send transaction
@@ -87,16 +152,21 @@ impl PrivateTokenContract {
         Ok(tx_hash)
     }
 
-    /// Transfer tokens privately
-    pub async fn transfer(&self, proof: Proof) -> Result<String> {
+    /// Transfer tokens privately, publishing the recipient's encrypted note
+    ///
+    /// The proof is verified locally first, as for [`Self::mint`].
+    pub async fn transfer(&self, proof: Proof, encrypted_note: Vec<u8>) -> Result<String> {
+        self.check_proof(&proof, CircuitKind::Transfer)?;
+        self.check_verifier(CircuitKind::Transfer).await?;
         tracing::info!("Submitting transfer transaction...");
         
         // Convert proof to contract format
         let proof_bytes = Bytes::from(proof.proof);
+        let encrypted_note = Bytes::from(encrypted_note);
         let public_inputs: Vec<FixedBytes<32>> = proof
             .public_inputs
             .iter()
-            .map(|p| FixedBytes::from_slice(p))
+            .map(|p| FixedBytes::from(*p))
             .collect();
 
         // Build and send transaction
@@ -109,16 +179,154 @@ impl PrivateTokenContract {
         Ok(tx_hash)
     }
 
+    /// The local verifier, which submissions need
+    fn verifier(&self, kind: CircuitKind) -> Result<&ProofGenerator> {
+        self.verifier.as_ref().ok_or_else(|| {
+            ClientError::ProofError(format!(
+                "No verifier configured; refusing to submit an unchecked {} proof",
+                kind
+            ))
+        })
+    }
+
+    /// Refuse to submit a proof that doesn't verify locally
+    fn check_proof(&self, proof: &Proof, kind: CircuitKind) -> Result<()> {
+        if !self.verifier(kind)?.verify_proof(proof, kind)? {
+            return Err(ClientError::ProofError(format!(
+                "{} proof does not verify locally; not submitting it",
+                kind
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that the contract's verifier for a circuit holds the local circuit's
+    /// verification key
+    ///
+    /// A verifier generated for another circuit, or from an older build of this
+    /// one, would reject every proof with an opaque `InvalidProof` revert.
+    pub async fn check_verifier(&self, kind: CircuitKind) -> Result<()> {
+        let prover = self.verifier(kind)?;
+        let expected = prover.verification_key(kind)?;
+        let address = self.verifier_address(kind).await?;
+        let code = self.get_code(address).await?;
+        let constants = pushed_constants(&code);
+
+        let other = match kind {
+            CircuitKind::Mint => CircuitKind::Transfer,
+            CircuitKind::Transfer => CircuitKind::Mint,
+        };
+        let reason = if code.is_empty() {
+            "there is no contract at that address".to_string()
+        } else if embeds_vk(&constants, &expected.key)? {
+            return Ok(());
+        } else if embeds_vk(&constants, &prover.verification_key(other)?.key)? {
+            format!("it was generated for the {} circuit", other)
+        } else {
+            "it holds a different verification key".to_string()
+        };
+
+        Err(ClientError::VerifierMismatch {
+            circuit: kind.to_string(),
+            verifier: address.to_string(),
+            expected: expected.hash_hex(),
+            reason,
+        })
+    }
+
+    /// Address of the contract's verifier for a circuit
+    pub async fn verifier_address(&self, kind: CircuitKind) -> Result<Address> {
+        match kind {
+            CircuitKind::Mint => Ok(self.call(IPrivateToken::mintVerifierCall {}).await?._0),
+            CircuitKind::Transfer => {
+                Ok(self.call(IPrivateToken::transferVerifierCall {}).await?._0)
+            }
+        }
+    }
+
+    /// Deployed bytecode at an address (`eth_getCode`)
+    pub async fn get_code(&self, address: Address) -> Result<Vec<u8>> {
+        let code = self.provider()?.get_code_at(address).await.map_err(|e| {
+            ClientError::ContractError(format!("Failed to fetch the code at {}: {}", address, e))
+        })?;
+        Ok(code.to_vec())
+    }
+
+    /// Call a view function of the PrivateToken contract (`eth_call`)
+    async fn call<C: SolCall>(&self, call: C) -> Result<C::Return> {
+        let request = TransactionRequest::default()
+            .to(self.contract_address()?)
+            .input(Bytes::from(call.abi_encode()).into());
+        let output = self.provider()?.call(&request).await.map_err(|e| {
+            ClientError::ContractError(format!("{} failed: {}", C::SIGNATURE, e))
+        })?;
+        C::abi_decode_returns(&output, true).map_err(|e| {
+            ClientError::ContractError(format!("{} returned malformed data: {}", C::SIGNATURE, e))
+        })
+    }
+
     /// Check if a commitment exists on-chain
-    pub async fn has_commitment(&self, commitment: &[u8; 32]) -> Result<bool> {
+    pub async fn has_commitment(&self, commitment: &Field) -> Result<bool> {
         // TODO: Implement actual contract call
         Ok(false)
     }
 
     /// Check if a nullifier has been used
-    pub async fn is_nullifier_used(&self, nullifier: &[u8; 32]) -> Result<bool> {
-        // TODO: Implement actual contract call
-        Ok(false)
+    pub async fn is_nullifier_used(&self, nullifier: &Field) -> Result<bool> {
+        let call = IPrivateToken::isNullifierUsedCall {
+            nullifier: (*nullifier).into(),
+        };
+        Ok(self.call(call).await?._0)
+    }
+
+    /// Look up what happened to a submitted transaction
+    pub async fn get_transaction_status(&self, tx_hash: &str) -> Result<TxStatus> {
+        let hash = B256::from_str(tx_hash).map_err(|e| {
+            ClientError::InvalidInput(format!("Invalid transaction hash {}: {}", tx_hash, e))
+        })?;
+        let provider = self.provider()?;
+        let lookup_failed =
+            |e| ClientError::ContractError(format!("Failed to look up {}: {}", tx_hash, e));
+
+        let receipt = provider
+            .get_transaction_receipt(hash)
+            .await
+            .map_err(lookup_failed)?;
+        if let Some(receipt) = receipt {
+            return Ok(match (receipt.status(), receipt.block_number) {
+                (true, Some(block_number)) => TxStatus::Confirmed { block_number },
+                (true, None) => TxStatus::Pending,
+                (false, _) => TxStatus::Failed,
+            });
+        }
+
+        let known = provider
+            .get_transaction_by_hash(hash)
+            .await
+            .map_err(lookup_failed)?;
+        Ok(match known {
+            Some(_) => TxStatus::Pending,
+            None => TxStatus::Unknown,
+        })
+    }
+
+    /// Fetch encrypted notes, recipient and change, from `PrivateTransfer` events
+    /// since a block
+    pub async fn get_encrypted_notes(&self, from_block: u64) -> Result<Vec<EncryptedNote>> {
+        let filter = Filter::new()
+            .address(self.contract_address()?)
+            .event_signature(IPrivateToken::PrivateTransfer::SIGNATURE_HASH)
+            .from_block(BlockNumberOrTag::Number(from_block));
+        let logs = self
+            .provider()?
+            .get_logs(&filter)
+            .await
+            .map_err(|e| ClientError::ContractError(format!("Failed to fetch transfers: {}", e)))?;
+        let mut notes = Vec::new();
+        for log in &logs {
+            notes.extend(encrypted_notes_from_log(log)?);
+        }
+        Ok(notes)
     }
 
     /// Get the total commitment count
@@ -128,26 +336,166 @@ impl PrivateTokenContract {
     }
 }
 
-/// Example implementation using alloy for actual contract interaction
-/// This is commented out as it requires async runtime and network access
-mod implementation_example {
-    /*
-    use alloy::providers::{Provider, ProviderBuilder};
-    use alloy::signers::local::PrivateKeySigner;
-    use alloy::network::EthereumWallet;
-    
-    pub async fn create_provider(config: &ContractConfig) -> Result<impl Provider> {
-        let signer: PrivateKeySigner = config.private_key.parse()
-            .map_err(|e| ClientError::InvalidInput(format!("Invalid private key: {}", e)))?;
-        
-        let wallet = EthereumWallet::from(signer);
-        
-        let provider = ProviderBuilder::new()
-            .with_recommended_fillers()
-            .wallet(wallet)
-            .on_http(config.rpc_url.parse().unwrap());
-        
-        Ok(provider)
+/// The recipient's note and, if published, the sender's change from a
+/// `PrivateTransfer` log
+fn encrypted_notes_from_log(log: &Log) -> Result<Vec<EncryptedNote>> {
+    let event = IPrivateToken::PrivateTransfer::decode_log_data(log.data(), true)
+        .map_err(|e| ClientError::ContractError(format!("Malformed PrivateTransfer log: {}", e)))?;
+    let (recipient, change) = note::decode_transfer_notes(&event.encryptedNote);
+
+    let mut notes = vec![EncryptedNote {
+        commitment: Field::try_from(event.recipientOutput)?,
+        ciphertext: recipient.to_vec(),
+        block_number: log.block_number,
+    }];
+    if let Some(change) = change {
+        notes.push(EncryptedNote {
+            commitment: Field::try_from(event.senderOutput)?,
+            ciphertext: change.to_vec(),
+            block_number: log.block_number,
+        });
+    }
+    Ok(notes)
+}
+
+/// Values of every `PUSH` in EVM bytecode, without leading zero bytes
+fn pushed_constants(code: &[u8]) -> HashSet<&[u8]> {
+    let mut constants = HashSet::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let opcode = code[pc];
+        pc += 1;
+        // PUSH1 to PUSH32
+        if (0x60..=0x7f).contains(&opcode) {
+            let end = (pc + usize::from(opcode - 0x5f)).min(code.len());
+            constants.insert(strip_leading_zeros(&code[pc..end]));
+            pc = end;
+        }
+    }
+    constants
+}
+
+/// Whether a verifier pushes every curve point coordinate of a verification key
+///
+/// `bb` writes an EVM verification key as 32-byte words: a few sizes, then the
+/// coordinates of each commitment. A generated verifier inlines the coordinates as
+/// `uint256` constants in `loadVerificationKey()`, which the compiler emits as
+/// pushes. Words that fit in 8 bytes are sizes or trivial points and are skipped,
+/// since the compiler may fold them.
+fn embeds_vk(constants: &HashSet<&[u8]>, vk: &[u8]) -> Result<bool> {
+    if !vk.len().is_multiple_of(32) {
+        return Err(ClientError::ProofError(format!(
+            "Verification key is {} bytes, not a multiple of 32",
+            vk.len()
+        )));
+    }
+    let mut coordinates = vk
+        .chunks(32)
+        .map(strip_leading_zeros)
+        .filter(|word| word.len() > 8)
+        .peekable();
+    if coordinates.peek().is_none() {
+        return Err(ClientError::ProofError(
+            "Verification key has no curve points".to_string(),
+        ));
+    }
+    Ok(coordinates.all(|word| constants.contains(word)))
+}
+
+fn strip_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::U256;
+
+    /// A key with a header of sizes and two points, whose coordinates start with `seed`
+    fn vk(seed: u8) -> Vec<u8> {
+        let mut key = Vec::new();
+        for size in [1u64 << 12, 12, 2, 1] {
+            key.extend(Field::from(size).to_bytes());
+        }
+        for coordinate in 0..4u8 {
+            let mut word = [seed; 32];
+            // Coordinates are below the field modulus, and sometimes a byte shorter
+            word[0] = 0;
+            word[1] = coordinate;
+            key.extend(word);
+        }
+        key
+    }
+
+    /// Bytecode storing each coordinate of a key as `loadVerificationKey()` does
+    fn verifier_code(key: &[u8]) -> Vec<u8> {
+        // PUSH1 0x80 PUSH1 0x40 MSTORE
+        let mut code = vec![0x60, 0x80, 0x60, 0x40, 0x52];
+        for word in key.chunks(32).skip(4) {
+            let value = strip_leading_zeros(word);
+            code.push(0x5f + value.len() as u8);
+            code.extend(value);
+            // PUSH1 0x00 MSTORE
+            code.extend([0x60, 0x00, 0x52]);
+        }
+        code.push(0x00);
+        code
+    }
+
+    #[test]
+    fn test_embeds_vk() {
+        let key = vk(0x5a);
+        let code = verifier_code(&key);
+        let constants = pushed_constants(&code);
+
+        assert!(embeds_vk(&constants, &key).unwrap());
+        assert!(!embeds_vk(&constants, &vk(0x5b)).unwrap());
+        assert!(!embeds_vk(&pushed_constants(&code[..code.len() / 2]), &key).unwrap());
+        assert!(embeds_vk(&constants, &key[..40]).is_err());
+        assert!(embeds_vk(&constants, &key[..128]).is_err());
+
+        // Coordinates inside the data of a longer push are not pushed themselves
+        let mut hidden = vec![0x7f, 0xff];
+        hidden.extend(&key[4 * 32 + 1..5 * 32]);
+        assert!(!pushed_constants(&hidden).contains(strip_leading_zeros(&key[4 * 32..5 * 32])));
+    }
+
+    fn transfer_log(encrypted_note: Vec<u8>) -> Log {
+        let event = IPrivateToken::PrivateTransfer {
+            nullifier: FixedBytes([1; 32]),
+            senderOutput: Field::from(41u64).into(),
+            recipientOutput: Field::from(42u64).into(),
+            timestamp: U256::from(1_700_000_000u64),
+            encryptedNote: Bytes::from(encrypted_note),
+        };
+        Log {
+            inner: alloy_primitives::Log {
+                address: Address::ZERO,
+                data: event.encode_log_data(),
+            },
+            block_number: Some(7),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_encrypted_notes_from_log() {
+        let notes = encrypted_notes_from_log(&transfer_log(vec![0xee; 8])).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].commitment, Field::from(42u64));
+        assert_eq!(notes[0].ciphertext, vec![0xee; 8]);
+        assert_eq!(notes[0].block_number, Some(7));
+
+        let recipient = [0xaa; note::ENCRYPTED_NOTE_LEN];
+        let change = [0xbb; note::ENCRYPTED_NOTE_LEN];
+        let log = transfer_log(note::encode_transfer_notes(&recipient, &change));
+        let notes = encrypted_notes_from_log(&log).unwrap();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].commitment, Field::from(42u64));
+        assert_eq!(notes[0].ciphertext, recipient);
+        assert_eq!(notes[1].commitment, Field::from(41u64));
+        assert_eq!(notes[1].ciphertext, change);
+        assert_eq!(notes[1].block_number, Some(7));
     }
-    */
 }
@@ -1,46 +1,201 @@
 //! Cryptographic utilities for the private token client
 
-use sha2::{Sha256, Digest};
+use std::fmt;
+use std::str::FromStr;
+
+use acvm::FieldElement;
+use ark_ec::CurveGroup;
+use ark_ff::{BigInteger, PrimeField};
+use ark_grumpkin::{Fq, Fr};
+use bn254_blackbox_solver::{derive_generators, poseidon_hash};
 use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ClientError, Result};
+use crate::field::{Field, MODULUS};
+
+/// Domain separator Noir uses for the default Pedersen generators
+const PEDERSEN_DOMAIN_SEPARATOR: &[u8] = b"DEFAULT_DOMAIN_SEPARATOR";
+
+/// Domain separator for the generator that absorbs the input length
+const PEDERSEN_LENGTH_SEPARATOR: &[u8] = b"pedersen_hash_length";
+
+/// Version of the hash construction for keys, commitments and nullifiers
+///
+/// Bump this whenever a derived value changes, so stored state from an older
+/// client is detected rather than silently misread. Version 1 hashed every role
+/// in the same domain.
+pub const HASH_VERSION: u32 = 2;
+
+/// Domain separator for viewing key derivation
+///
+/// Pedersen uses the separator as the generator offset, as Noir's
+/// `pedersen_hash_with_separator` does; Poseidon2 absorbs it as the first input.
+/// Offsets are spaced so the generators of different roles never overlap.
+pub const DOMAIN_VIEWING_KEY: u32 = 16;
+
+/// Domain separator for address derivation
+pub const DOMAIN_ADDRESS: u32 = 32;
+
+/// Domain separator for note commitments
+pub const DOMAIN_COMMITMENT: u32 = 48;
+
+/// Domain separator for nullifiers
+pub const DOMAIN_NULLIFIER: u32 = 64;
+
+/// Generate a random secret field element
+pub fn generate_secret() -> Field {
+    // Sample 64 bytes so the reduction modulo the field is statistically uniform
+    let mut bytes = [0u8; 64];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    Field::from_bytes_reduce(&bytes)
+}
+
+/// Generate a random per-note blinding factor
+pub fn generate_blinding() -> Field {
+    generate_secret()
+}
 
-/// Generate a random 32-byte secret
-pub fn generate_secret() -> [u8; 32] {
-    let mut secret = [0u8; 32];
-    rand::thread_rng().fill_bytes(&mut secret);
-    secret
+/// Compute the Pedersen hash over BN254, matching Noir's `std::hash::pedersen_hash`
+///
+/// The hash is the x-coordinate of `sum(input_i * G_i) + N * H` on the Grumpkin
+/// curve, where `G_i` are the default domain generators and `H` is the length generator.
+pub fn pedersen_hash(inputs: &[Field]) -> Field {
+    pedersen_hash_with_separator(inputs, 0)
 }
 
-/// Compute a simple hash (placeholder for Pedersen hash)
-/// In production, use the actual Pedersen hash from Noir/Barretenberg
-pub fn pedersen_hash(inputs: &[&[u8; 32]]) -> [u8; 32] {
-    // This is a placeholder using SHA256
-    // TODO: Replace with actual Pedersen hash from bn254_blackbox_solver
-    let mut hasher = Sha256::new();
-    for input in inputs {
-        hasher.update(input);
+/// Compute the Pedersen hash with the generators starting at `separator`, matching
+/// Noir's `std::hash::pedersen_hash_with_separator`
+pub fn pedersen_hash_with_separator(inputs: &[Field], separator: u32) -> Field {
+    let generators = derive_generators(PEDERSEN_DOMAIN_SEPARATOR, inputs.len() as u32, separator);
+    let length_generator = derive_generators(PEDERSEN_LENGTH_SEPARATOR, 1, 0)[0];
+
+    let mut result = length_generator * Fr::from(inputs.len() as u64);
+    for (input, generator) in inputs.iter().zip(generators) {
+        result += generator * field_to_scalar(input);
     }
-    let result = hasher.finalize();
-    let mut output = [0u8; 32];
-    output.copy_from_slice(&result);
-    output
+
+    Field::from_bytes_reduce(&result.into_affine().x.into_bigint().to_bytes_be())
 }
 
-/// Derive address from secret key
-pub fn derive_address(secret: &[u8; 32]) -> [u8; 32] {
-    pedersen_hash(&[secret])
+/// Compute the Poseidon2 hash over BN254, matching Noir's `Poseidon2::hash(inputs, N)`
+pub fn poseidon2_hash(inputs: &[Field]) -> Field {
+    let inputs: Vec<FieldElement> = inputs.iter().map(|&input| input.into()).collect();
+    poseidon_hash(&inputs, false)
+        .expect("Poseidon2 accepts any number of field elements")
+        .into()
 }
 
-/// Compute commitment: Hash(address, balance, nonce)
-pub fn compute_commitment(address: &[u8; 32], balance: u128, nonce: u64) -> [u8; 32] {
-    let balance_bytes = u128_to_bytes32(balance);
-    let nonce_bytes = u64_to_bytes32(nonce);
-    pedersen_hash(&[address, &balance_bytes, &nonce_bytes])
+/// Hash function used for note commitments and nullifiers
+///
+/// Account keys and addresses are always derived with Pedersen, so an address is
+/// the same under every scheme and can hold notes of either kind. Each note is
+/// spent with the circuit variant matching the scheme it was committed with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashScheme {
+    /// Pedersen hash, used by the `mint` and `private_transfer` circuits
+    #[default]
+    Pedersen,
+    /// Poseidon2 hash, used by the `*_poseidon2` circuits; cheaper to prove
+    Poseidon2,
 }
 
-/// Compute nullifier: Hash(secret, nonce)
-pub fn compute_nullifier(secret: &[u8; 32], nonce: u64) -> [u8; 32] {
-    let nonce_bytes = u64_to_bytes32(nonce);
-    pedersen_hash(&[secret, &nonce_bytes])
+impl HashScheme {
+    /// All supported schemes
+    pub const ALL: [HashScheme; 2] = [HashScheme::Pedersen, HashScheme::Poseidon2];
+
+    /// Hash field elements with this scheme in the given domain
+    pub fn hash(self, domain: u32, inputs: &[Field]) -> Field {
+        match self {
+            HashScheme::Pedersen => pedersen_hash_with_separator(inputs, domain),
+            HashScheme::Poseidon2 => {
+                let mut tagged = Vec::with_capacity(inputs.len() + 1);
+                tagged.push(Field::from(domain as u64));
+                tagged.extend_from_slice(inputs);
+                poseidon2_hash(&tagged)
+            }
+        }
+    }
+
+    /// Name of the Noir package implementing `circuit` with this scheme
+    pub fn circuit_package(self, circuit: &str) -> String {
+        match self {
+            HashScheme::Pedersen => circuit.to_string(),
+            HashScheme::Poseidon2 => format!("{}_poseidon2", circuit),
+        }
+    }
+}
+
+impl fmt::Display for HashScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashScheme::Pedersen => f.write_str("pedersen"),
+            HashScheme::Poseidon2 => f.write_str("poseidon2"),
+        }
+    }
+}
+
+impl FromStr for HashScheme {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pedersen" => Ok(HashScheme::Pedersen),
+            "poseidon2" => Ok(HashScheme::Poseidon2),
+            _ => Err(ClientError::InvalidInput(format!("Unknown hash scheme: {}", s))),
+        }
+    }
+}
+
+/// Lift a BN254 field element into the Grumpkin scalar field
+pub(crate) fn field_to_scalar(field: &Field) -> Fr {
+    // The BN254 scalar field is the Grumpkin base field, which is smaller than the
+    // Grumpkin scalar field, so every field element is a valid scalar.
+    let field = Fq::from_be_bytes_mod_order(field.as_bytes());
+    Fr::from_bigint(field.into_bigint()).expect("BN254 field element fits in Grumpkin scalar field")
+}
+
+/// Derive viewing key from secret key: Hash(secret)
+///
+/// The viewing key identifies an account's notes and nullifiers, but cannot
+/// produce a spend proof without the secret it was derived from.
+pub fn derive_viewing_key(secret: &Field) -> Field {
+    pedersen_hash_with_separator(&[*secret], DOMAIN_VIEWING_KEY)
+}
+
+/// Derive address from viewing key: Hash(viewing_key)
+pub fn address_from_viewing_key(viewing_key: &Field) -> Field {
+    pedersen_hash_with_separator(&[*viewing_key], DOMAIN_ADDRESS)
+}
+
+/// Derive address from secret key: Hash(Hash(secret))
+pub fn derive_address(secret: &Field) -> Field {
+    address_from_viewing_key(&derive_viewing_key(secret))
+}
+
+/// Compute commitment: Hash(address, balance, nonce, blinding)
+///
+/// The blinding keeps two notes with the same owner, amount and nonce distinct.
+pub fn compute_commitment(
+    scheme: HashScheme,
+    address: &Field,
+    balance: u128,
+    nonce: u64,
+    blinding: &Field,
+) -> Field {
+    let inputs = [*address, Field::from(balance), Field::from(nonce), *blinding];
+    scheme.hash(DOMAIN_COMMITMENT, &inputs)
+}
+
+/// Compute nullifier: Hash(viewing_key, nonce, blinding)
+pub fn compute_nullifier(
+    scheme: HashScheme,
+    viewing_key: &Field,
+    nonce: u64,
+    blinding: &Field,
+) -> Field {
+    scheme.hash(DOMAIN_NULLIFIER, &[*viewing_key, Field::from(nonce), *blinding])
 }
 
 /// Convert u128 to 32-byte array (big-endian, left-padded)
@@ -62,19 +217,51 @@ pub fn bytes32_to_hex(bytes: &[u8; 32]) -> String {
     format!("0x{}", hex::encode(bytes))
 }
 
-/// Convert hex string to bytes32
-pub fn hex_to_bytes32(hex_str: &str) -> Result<[u8; 32], hex::FromHexError> {
-    let hex_str = hex_str.strip_prefix("0x").unwrap_or(hex_str);
-    let bytes = hex::decode(hex_str)?;
+/// How `hex_to_bytes32_with` treats input shorter than 32 bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexPadding {
+    /// Require exactly 64 hex digits
+    Strict,
+    /// Left-pad short input with zero bytes
+    LeftPad,
+}
+
+/// Convert a hex string of exactly 32 bytes to bytes32
+///
+/// Rejects oversize, short, odd-length and non-hex input as well as values
+/// at or above the BN254 field modulus.
+pub fn hex_to_bytes32(hex_str: &str) -> Result<[u8; 32]> {
+    hex_to_bytes32_with(hex_str, HexPadding::Strict)
+}
+
+/// Convert hex string to bytes32 with an explicit padding mode
+pub fn hex_to_bytes32_with(hex_str: &str, padding: HexPadding) -> Result<[u8; 32]> {
+    let digits = hex_str.strip_prefix("0x").unwrap_or(hex_str);
+    if !digits.len().is_multiple_of(2) {
+        return Err(ClientError::HexOddLength(digits.len()));
+    }
+    if digits.len() > 64 {
+        return Err(ClientError::HexTooLong(digits.len()));
+    }
+    if digits.len() < 64 && padding == HexPadding::Strict {
+        return Err(ClientError::HexTooShort(digits.len()));
+    }
+
+    let bytes = hex::decode(digits)?;
     let mut result = [0u8; 32];
-    if bytes.len() == 32 {
-        result.copy_from_slice(&bytes);
-    } else if bytes.len() < 32 {
-        result[32 - bytes.len()..].copy_from_slice(&bytes);
+    result[32 - bytes.len()..].copy_from_slice(&bytes);
+
+    if result >= MODULUS {
+        return Err(ClientError::FieldOverflow(hex_str.to_string()));
     }
     Ok(result)
 }
 
+/// Convert a strict 32-byte hex string to a field element
+pub fn hex_to_field(hex_str: &str) -> Result<Field> {
+    Field::from_bytes(hex_to_bytes32(hex_str)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -99,27 +286,195 @@ mod tests {
         let address = generate_secret();
         let balance = 100u128;
         let nonce = 1u64;
+        let blinding = generate_blinding();
+        let scheme = HashScheme::Pedersen;
         
-        let commitment1 = compute_commitment(&address, balance, nonce);
-        let commitment2 = compute_commitment(&address, balance, nonce);
+        let commitment1 = compute_commitment(scheme, &address, balance, nonce, &blinding);
+        let commitment2 = compute_commitment(scheme, &address, balance, nonce, &blinding);
         assert_eq!(commitment1, commitment2);
     }
 
+    #[test]
+    fn test_blinding_separates_identical_notes() {
+        let address = generate_secret();
+        let scheme = HashScheme::Pedersen;
+        let commitment1 = compute_commitment(scheme, &address, 25, 0, &generate_blinding());
+        let commitment2 = compute_commitment(scheme, &address, 25, 0, &generate_blinding());
+        assert_ne!(commitment1, commitment2);
+    }
+
     #[test]
     fn test_nullifier_deterministic() {
         let secret = generate_secret();
         let nonce = 1u64;
+        let blinding = generate_blinding();
         
-        let nullifier1 = compute_nullifier(&secret, nonce);
-        let nullifier2 = compute_nullifier(&secret, nonce);
+        let nullifier1 = compute_nullifier(HashScheme::Pedersen, &secret, nonce, &blinding);
+        let nullifier2 = compute_nullifier(HashScheme::Pedersen, &secret, nonce, &blinding);
         assert_eq!(nullifier1, nullifier2);
     }
 
+    /// `recipient_secret` from `circuits/mint/Prover.toml`
+    const PROVER_SECRET: u64 = 1234567890;
+
+    /// `blinding` from `circuits/mint/Prover.toml`
+    const PROVER_BLINDING: u64 = 987654321;
+
+    #[test]
+    fn test_derive_address_matches_circuit() {
+        let secret = Field::from(PROVER_SECRET);
+        let viewing_key = derive_viewing_key(&secret);
+        assert_eq!(
+            viewing_key.to_hex(),
+            "0x1fd5d8c42848e3bd4f591f197b4c1fba6ce2efcfcbe52fffe5996655663dd131"
+        );
+        assert_eq!(
+            derive_address(&secret).to_hex(),
+            "0x08216bc567d26a5f22b8c9eb40fd534fb3aa952311f05a64103d4b2826f53161"
+        );
+        assert_eq!(address_from_viewing_key(&viewing_key), derive_address(&secret));
+    }
+
+    #[test]
+    fn test_commitment_matches_mint_prover_toml() {
+        let address = derive_address(&Field::from(PROVER_SECRET));
+        let blinding = Field::from(PROVER_BLINDING);
+        let commitment = compute_commitment(HashScheme::Pedersen, &address, 100, 1, &blinding);
+        assert_eq!(
+            commitment.to_hex(),
+            "0x1cfb071dc98daaef943733c461cd317a80eddf0f2a81535f509c02acc14ba5bc"
+        );
+    }
+
+    #[test]
+    fn test_nullifier_matches_circuit() {
+        let viewing_key = derive_viewing_key(&Field::from(PROVER_SECRET));
+        let blinding = Field::from(PROVER_BLINDING);
+        let nullifier = compute_nullifier(HashScheme::Pedersen, &viewing_key, 1, &blinding);
+        assert_eq!(
+            nullifier.to_hex(),
+            "0x07f257eef4eefa8de08bd1db1b6821a6ae88fd8a0a0d17d2a3225a8b4f422001"
+        );
+    }
+
+    #[test]
+    fn test_poseidon2_hash_matches_noir() {
+        // `Poseidon2::hash([1, 2], 2)` in Noir
+        assert_eq!(
+            poseidon2_hash(&[Field::from(1u64), Field::from(2u64)]).to_hex(),
+            "0x038682aa1cb5ae4e0a3f13da432a95c77c5c111f6f030faf9cad641ce1ed7383"
+        );
+    }
+
+    #[test]
+    fn test_poseidon2_commitment_matches_mint_prover_toml() {
+        let address = derive_address(&Field::from(PROVER_SECRET));
+        let blinding = Field::from(PROVER_BLINDING);
+        let commitment = compute_commitment(HashScheme::Poseidon2, &address, 100, 1, &blinding);
+        assert_eq!(
+            commitment.to_hex(),
+            "0x2eabb912b34321e1184840ed920d1379c2129ed5964d7c9ad10c6c6db21b1ce5"
+        );
+    }
+
+    #[test]
+    fn test_poseidon2_outputs_match_transfer_prover_toml() {
+        let address = derive_address(&Field::from(PROVER_SECRET));
+        let scheme = HashScheme::Poseidon2;
+        let change = compute_commitment(scheme, &address, 75, 2, &Field::from(1122334455u64));
+        assert_eq!(
+            change.to_hex(),
+            "0x0e2b170ea4d162be06eb0b6742f7a1ff956f65c36005f85ea58d7f7dbbfa2a01"
+        );
+        let recipient = Field::from(9876543210u64);
+        let received = compute_commitment(scheme, &recipient, 25, 0, &Field::from(5544332211u64));
+        assert_eq!(
+            received.to_hex(),
+            "0x1fc1479318fe2a45a96da1cabf3af651e08c180ffab8c07570b45767d0cab0b7"
+        );
+    }
+
+    #[test]
+    fn test_poseidon2_nullifier_matches_transfer_prover_toml() {
+        let viewing_key = derive_viewing_key(&Field::from(PROVER_SECRET));
+        let blinding = Field::from(PROVER_BLINDING);
+        let nullifier = compute_nullifier(HashScheme::Poseidon2, &viewing_key, 1, &blinding);
+        assert_eq!(
+            nullifier.to_hex(),
+            "0x0c39038543c64c15590fe160d1e0c7ea19d3ab1b23085ae528d17fd64381ee6b"
+        );
+    }
+
+    #[test]
+    fn test_hash_schemes_differ() {
+        let inputs = [generate_secret(), Field::from(100u64)];
+        assert_eq!(HashScheme::Pedersen.hash(0, &inputs), pedersen_hash(&inputs));
+        assert_ne!(pedersen_hash(&inputs), poseidon2_hash(&inputs));
+    }
+
+    #[test]
+    fn test_roles_are_domain_separated() {
+        let value = generate_secret();
+        for scheme in HashScheme::ALL {
+            let hashes = [DOMAIN_VIEWING_KEY, DOMAIN_ADDRESS, DOMAIN_COMMITMENT, DOMAIN_NULLIFIER]
+                .map(|domain| scheme.hash(domain, &[value]));
+            for (i, a) in hashes.iter().enumerate() {
+                for b in &hashes[i + 1..] {
+                    assert_ne!(a, b);
+                }
+            }
+        }
+        assert_ne!(derive_viewing_key(&value), pedersen_hash(&[value]));
+    }
+
+    #[test]
+    fn test_hash_scheme_parsing() {
+        for scheme in HashScheme::ALL {
+            assert_eq!(scheme.to_string().parse::<HashScheme>().unwrap(), scheme);
+            let json = serde_json::to_string(&scheme).unwrap();
+            assert_eq!(json, format!("\"{}\"", scheme));
+        }
+        assert!("sha256".parse::<HashScheme>().is_err());
+        assert_eq!(HashScheme::Poseidon2.circuit_package("mint"), "mint_poseidon2");
+    }
+
     #[test]
     fn test_hex_conversion() {
-        let bytes = generate_secret();
+        let bytes = generate_secret().to_bytes();
         let hex_str = bytes32_to_hex(&bytes);
         let recovered = hex_to_bytes32(&hex_str).unwrap();
         assert_eq!(bytes, recovered);
     }
+
+    #[test]
+    fn test_hex_rejects_oversize() {
+        let hex_str = format!("0x{}", "00".repeat(33));
+        assert!(matches!(hex_to_bytes32(&hex_str), Err(ClientError::HexTooLong(66))));
+    }
+
+    #[test]
+    fn test_hex_rejects_odd_length() {
+        assert!(matches!(hex_to_bytes32("0xabc"), Err(ClientError::HexOddLength(3))));
+    }
+
+    #[test]
+    fn test_hex_rejects_short_unless_padded() {
+        assert!(matches!(hex_to_bytes32("0xabcd"), Err(ClientError::HexTooShort(4))));
+
+        let padded = hex_to_bytes32_with("0xabcd", HexPadding::LeftPad).unwrap();
+        assert_eq!(padded, u64_to_bytes32(0xabcd));
+    }
+
+    #[test]
+    fn test_hex_rejects_above_modulus() {
+        let hex_str = format!("0x{}", "ff".repeat(32));
+        assert!(matches!(hex_to_bytes32(&hex_str), Err(ClientError::FieldOverflow(_))));
+        assert!(hex_to_bytes32(&bytes32_to_hex(&MODULUS)).is_err());
+    }
+
+    #[test]
+    fn test_hex_rejects_invalid_digits() {
+        let hex_str = format!("0x{}", "zz".repeat(32));
+        assert!(matches!(hex_to_bytes32(&hex_str), Err(ClientError::HexError(_))));
+    }
 }
@@ -7,6 +7,9 @@ pub enum ClientError {
     #[error("Insufficient balance: have {have}, need {need}")]
     InsufficientBalance { have: u128, need: u128 },
 
+    #[error("No single note covers {need} (largest is {largest}, total balance {total}); a transfer spends one note")]
+    NoSufficientNote { largest: u128, need: u128, total: u128 },
+
     #[error("Commitment not found: {0}")]
     CommitmentNotFound(String),
 
@@ -25,9 +28,53 @@ pub enum ClientError {
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
 
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] rusqlite::Error),
+
     #[error("Hex decode error: {0}")]
     HexError(#[from] hex::FromHexError),
 
+    #[error("Hex value too long: {0} digits, expected at most 64")]
+    HexTooLong(usize),
+
+    #[error("Hex value too short: {0} digits, expected 64")]
+    HexTooShort(usize),
+
+    #[error("Hex value has an odd number of digits: {0}")]
+    HexOddLength(usize),
+
+    #[error("Value exceeds BN254 field modulus: {0}")]
+    FieldOverflow(String),
+
+    #[error("State was written with hash version {found}, but this client uses version {expected}")]
+    HashVersionMismatch { found: u32, expected: u32 },
+
+    #[error("State was written with format version {found}, but this client supports up to {supported}")]
+    StateVersionTooNew { found: u32, supported: u32 },
+
+    #[error("Wrong password or corrupted wallet file")]
+    InvalidPassword,
+
+    #[error("Corrupt state file: {0}")]
+    CorruptState(String),
+
+    #[error("Wallet {0} is in use by another process")]
+    WalletLocked(String),
+
+    #[error("Invalid backup: {0}")]
+    InvalidBackup(String),
+
+    #[error("Backup conflicts with this wallet: {}", .0.join("; "))]
+    BackupConflict(Vec<String>),
+
+    #[error("{circuit} verifier at {verifier} doesn't match the local circuit: {reason} (expected VK hash {expected})")]
+    VerifierMismatch {
+        circuit: String,
+        verifier: String,
+        expected: String,
+        reason: String,
+    },
+
     #[error("Invalid input: {0}")]
     InvalidInput(String),
 }
@@ -0,0 +1,280 @@
+//! Canonical BN254 field element used throughout the client
+
+use std::fmt;
+use std::str::FromStr;
+
+use acvm::{AcirField, FieldElement};
+use alloy_primitives::FixedBytes;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+use crate::error::{ClientError, Result};
+
+/// The BN254 scalar field modulus, big-endian
+pub const MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x28, 0x33, 0xe8, 0x48, 0x79, 0xb9, 0x70, 0x91, 0x43, 0xe1, 0xf5, 0x93, 0xf0, 0x00, 0x00, 0x01,
+];
+
+/// A BN254 field element, stored as 32 big-endian bytes
+///
+/// Every constructor checks the value against the field modulus, so a `Field`
+/// can always be handed to a circuit without being silently reduced.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Field([u8; 32]);
+
+impl Field {
+    /// The zero element
+    pub const ZERO: Field = Field([0u8; 32]);
+
+    /// Create a field element from big-endian bytes, rejecting values >= modulus
+    pub fn from_bytes(bytes: [u8; 32]) -> Result<Self> {
+        if bytes >= MODULUS {
+            return Err(ClientError::FieldOverflow(format!("0x{}", hex::encode(bytes))));
+        }
+        Ok(Self(bytes))
+    }
+
+    /// Create a field element from arbitrary big-endian bytes, reducing modulo the field
+    pub fn from_bytes_reduce(bytes: &[u8]) -> Self {
+        FieldElement::from_be_bytes_reduce(bytes).into()
+    }
+
+    /// Parse a hex string (with or without `0x` prefix) of at most 64 digits
+    pub fn from_hex(hex_str: &str) -> Result<Self> {
+        let digits = hex_str.strip_prefix("0x").unwrap_or(hex_str);
+        if digits.is_empty() {
+            return Err(ClientError::InvalidInput("Empty hex field element".to_string()));
+        }
+        if digits.len() > 64 {
+            return Err(ClientError::FieldOverflow(hex_str.to_string()));
+        }
+
+        // Numeric hex may have an odd number of digits, e.g. "0x1"
+        let padded = format!("{:0>64}", digits);
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(&padded, &mut bytes)?;
+        Self::from_bytes(bytes)
+    }
+
+    /// Parse a decimal string
+    pub fn from_decimal(decimal: &str) -> Result<Self> {
+        if decimal.is_empty() {
+            return Err(ClientError::InvalidInput("Empty decimal field element".to_string()));
+        }
+
+        let mut bytes = [0u8; 32];
+        for c in decimal.chars() {
+            let digit = c.to_digit(10).ok_or_else(|| {
+                ClientError::InvalidInput(format!("Invalid decimal field element: {}", decimal))
+            })?;
+
+            // bytes = bytes * 10 + digit
+            let mut carry = digit;
+            for byte in bytes.iter_mut().rev() {
+                let value = (*byte as u32) * 10 + carry;
+                *byte = value as u8;
+                carry = value >> 8;
+            }
+            if carry != 0 {
+                return Err(ClientError::FieldOverflow(decimal.to_string()));
+            }
+        }
+
+        Self::from_bytes(bytes).map_err(|_| ClientError::FieldOverflow(decimal.to_string()))
+    }
+
+    /// Big-endian byte representation
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Borrow the big-endian byte representation
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// `0x`-prefixed, zero-padded hex representation
+    pub fn to_hex(&self) -> String {
+        format!("0x{}", hex::encode(self.0))
+    }
+
+    /// Whether this is the zero element
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0u8; 32]
+    }
+}
+
+impl From<u64> for Field {
+    fn from(value: u64) -> Self {
+        let mut bytes = [0u8; 32];
+        bytes[24..].copy_from_slice(&value.to_be_bytes());
+        Self(bytes)
+    }
+}
+
+impl From<u128> for Field {
+    fn from(value: u128) -> Self {
+        let mut bytes = [0u8; 32];
+        bytes[16..].copy_from_slice(&value.to_be_bytes());
+        Self(bytes)
+    }
+}
+
+impl From<FieldElement> for Field {
+    fn from(element: FieldElement) -> Self {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&element.to_be_bytes());
+        Self(bytes)
+    }
+}
+
+impl From<Field> for FieldElement {
+    fn from(field: Field) -> Self {
+        FieldElement::from_be_bytes_reduce(&field.0)
+    }
+}
+
+impl From<Field> for FixedBytes<32> {
+    fn from(field: Field) -> Self {
+        FixedBytes(field.0)
+    }
+}
+
+impl TryFrom<FixedBytes<32>> for Field {
+    type Error = ClientError;
+
+    fn try_from(bytes: FixedBytes<32>) -> Result<Self> {
+        Self::from_bytes(bytes.0)
+    }
+}
+
+impl Zeroize for Field {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl FromStr for Field {
+    type Err = ClientError;
+
+    /// Parse `0x`-prefixed hex or plain decimal, as used in `Prover.toml`
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.starts_with("0x") {
+            Self::from_hex(s)
+        } else {
+            Self::from_decimal(s)
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Field({})", self.to_hex())
+    }
+}
+
+impl Serialize for Field {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Deserialize an optional field element, treating an empty string as `None`
+///
+/// Older state files stored unknown secrets as `""` rather than `null`.
+pub fn deserialize_optional<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Field>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) if !s.is_empty() => s.parse().map(Some).map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULUS_DECIMAL: &str =
+        "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+
+    #[test]
+    fn test_hex_roundtrip() {
+        let hex_str = "0x1a69fa5d7de95cebe216e459c70574439885e1530bb4c736ab220e30a55b3b5f";
+        let field = Field::from_hex(hex_str).unwrap();
+        assert_eq!(Field::from_hex(&field.to_hex()).unwrap(), field);
+    }
+
+    #[test]
+    fn test_short_hex_is_numeric() {
+        assert_eq!(Field::from_hex("0x1").unwrap(), Field::from(1u64));
+        assert_eq!(Field::from_hex("ff").unwrap(), Field::from(255u64));
+    }
+
+    #[test]
+    fn test_decimal_parsing() {
+        assert_eq!(Field::from_decimal("1234567890").unwrap(), Field::from(1234567890u64));
+        assert_eq!(Field::from_str("100").unwrap(), Field::from(100u64));
+        assert!(Field::from_decimal("12a").is_err());
+    }
+
+    #[test]
+    fn test_rejects_modulus() {
+        assert!(matches!(
+            Field::from_bytes(MODULUS),
+            Err(ClientError::FieldOverflow(_))
+        ));
+        assert!(matches!(
+            Field::from_decimal(MODULUS_DECIMAL),
+            Err(ClientError::FieldOverflow(_))
+        ));
+        assert!(matches!(
+            Field::from_hex(&format!("0x{}", "ff".repeat(32))),
+            Err(ClientError::FieldOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_accepts_modulus_minus_one() {
+        let mut max = MODULUS;
+        max[31] -= 1;
+        let field = Field::from_bytes(max).unwrap();
+        assert_eq!(
+            Field::from_decimal(
+                "21888242871839275222246405745257275088548364400416034343698204186575808495616"
+            )
+            .unwrap(),
+            field
+        );
+    }
+
+    #[test]
+    fn test_field_element_roundtrip() {
+        let field = Field::from(987654321u64);
+        let element: FieldElement = field.into();
+        assert_eq!(Field::from(element), field);
+    }
+
+    #[test]
+    fn test_serde_as_hex_string() {
+        let field = Field::from(42u64);
+        let json = serde_json::to_string(&field).unwrap();
+        assert_eq!(json, format!("\"{}\"", field.to_hex()));
+        assert_eq!(serde_json::from_str::<Field>(&json).unwrap(), field);
+    }
+}
@@ -0,0 +1,200 @@
+//! Transaction history
+//!
+//! The wallet keeps an entry for every mint and transfer it makes and every note it
+//! receives, recording which notes each one consumed and created, so balances can
+//! be reconciled against what happened on-chain.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{ClientError, Result};
+use crate::field::Field;
+
+/// What an operation did, from the point of view of one of our accounts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    /// Tokens minted to the account
+    Mint,
+    /// Tokens transferred out of the account
+    Send,
+    /// Tokens transferred to the account
+    Receive,
+}
+
+impl OperationKind {
+    /// Name used in the CLI and the stored state
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OperationKind::Mint => "mint",
+            OperationKind::Send => "send",
+            OperationKind::Receive => "receive",
+        }
+    }
+
+    /// Whether the operation adds to the account's balance
+    pub fn is_credit(self) -> bool {
+        self != OperationKind::Send
+    }
+}
+
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Whether an operation's transaction has settled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    /// Submitted but not yet mined
+    Pending,
+    /// Mined
+    Confirmed,
+    /// Reverted or dropped; the operation had no effect
+    Failed,
+}
+
+impl OperationStatus {
+    /// Name used in the CLI and the stored state
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OperationStatus::Pending => "pending",
+            OperationStatus::Confirmed => "confirmed",
+            OperationStatus::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for OperationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OperationStatus {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        [
+            OperationStatus::Pending,
+            OperationStatus::Confirmed,
+            OperationStatus::Failed,
+        ]
+        .into_iter()
+        .find(|status| status.as_str() == s)
+        .ok_or_else(|| ClientError::InvalidInput(format!("unknown operation status: {}", s)))
+    }
+}
+
+/// One mint, transfer or received note
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// The note the tokens went to: the minted or received note, or the recipient's
+    /// note of a send. Entries are identified by their kind and id.
+    pub id: Field,
+    /// What the operation did
+    pub kind: OperationKind,
+    /// Whether its transaction has settled
+    pub status: OperationStatus,
+    /// Our account the operation belongs to
+    pub address: Field,
+    /// Tokens moved, not counting change returned to the sender
+    pub amount: u128,
+    /// The other side of a transfer, if known
+    pub counterparty: Option<Field>,
+    /// Nullifier published by a send
+    pub nullifier: Option<Field>,
+    /// Transaction the operation was submitted in, if this wallet submitted it
+    pub tx_hash: Option<String>,
+    /// Block the transaction was mined in, if known
+    pub block_number: Option<u64>,
+    /// When the wallet recorded the operation, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Commitments of the notes the operation spent
+    pub consumed: Vec<Field>,
+    /// Commitments of the notes the operation created
+    pub created: Vec<Field>,
+}
+
+impl HistoryEntry {
+    /// A confirmed entry timestamped now, with no counterparty, transaction or notes
+    pub fn new(kind: OperationKind, id: Field, address: Field, amount: u128) -> Self {
+        Self {
+            id,
+            kind,
+            status: OperationStatus::Confirmed,
+            address,
+            amount,
+            counterparty: None,
+            nullifier: None,
+            tx_hash: None,
+            block_number: None,
+            timestamp: unix_time(),
+            consumed: Vec::new(),
+            created: Vec::new(),
+        }
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Format a Unix timestamp as a UTC date and time, e.g. `2024-03-01 12:00:00`
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (days, seconds) = (timestamp / 86_400, timestamp % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_timestamp(1_709_294_400), "2024-03-01 12:00:00");
+    }
+
+    #[test]
+    fn test_status_round_trips() {
+        for status in [
+            OperationStatus::Pending,
+            OperationStatus::Confirmed,
+            OperationStatus::Failed,
+        ] {
+            assert_eq!(status.as_str().parse::<OperationStatus>().unwrap(), status);
+        }
+        assert!("settled".parse::<OperationStatus>().is_err());
+    }
+}
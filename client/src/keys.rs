@@ -0,0 +1,481 @@
+//! Hierarchical deterministic key derivation from a BIP-39 mnemonic
+
+use std::fmt;
+use std::str::FromStr;
+
+use bip39::{Language, Mnemonic};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::crypto::{self, HashScheme};
+use crate::error::{ClientError, Result};
+use crate::field::{self, Field};
+use crate::note;
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// HMAC key used to derive the master key from a BIP-39 seed
+const MASTER_KEY_DOMAIN: &[u8] = b"private-token master key";
+
+/// Derivation tag for account spending secrets
+const SPENDING_KEY_TAG: &[u8] = b"spend";
+
+/// Derivation tag for the incoming viewing key
+const INCOMING_VIEWING_KEY_TAG: &[u8] = b"incoming viewing key";
+
+/// Derivation tag for the outgoing viewing key
+const OUTGOING_VIEWING_KEY_TAG: &[u8] = b"outgoing viewing key";
+
+/// Prefix of an encoded payment address
+pub const PAYMENT_ADDRESS_PREFIX: &str = "ptk1";
+
+/// Length of the checksum at the end of an encoded payment address
+const PAYMENT_ADDRESS_CHECKSUM_LEN: usize = 4;
+
+/// Entropy size for newly generated mnemonics (24 words)
+const MNEMONIC_ENTROPY_BYTES: usize = 32;
+
+/// Generate a new random 24-word English mnemonic
+pub fn generate_mnemonic() -> Mnemonic {
+    let mut entropy = [0u8; MNEMONIC_ENTROPY_BYTES];
+    rand::thread_rng().fill_bytes(&mut entropy);
+    Mnemonic::from_entropy_in(Language::English, &entropy)
+        .expect("32 bytes is a valid mnemonic entropy length")
+}
+
+/// Parse an English mnemonic, normalizing case and whitespace
+pub fn parse_mnemonic(phrase: &str) -> Result<Mnemonic> {
+    let normalized = phrase
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ");
+    Mnemonic::parse_in_normalized(Language::English, &normalized)
+        .map_err(|e| ClientError::InvalidInput(format!("Invalid mnemonic: {}", e)))
+}
+
+/// Mnemonic phrase that every HD account is derived from
+///
+/// Like [`SpendingKey`], the phrase is wiped from memory when dropped and redacted
+/// from `Debug` output.
+#[derive(Clone, PartialEq, Eq, Zeroize, ZeroizeOnDrop, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MnemonicPhrase(String);
+
+impl MnemonicPhrase {
+    /// Wrap a phrase
+    pub fn new(phrase: String) -> Self {
+        Self(phrase)
+    }
+
+    /// The phrase of a parsed mnemonic
+    pub fn from_mnemonic(mnemonic: &Mnemonic) -> Self {
+        Self(mnemonic.to_string())
+    }
+
+    /// Parse the phrase (see [`parse_mnemonic`])
+    pub fn to_mnemonic(&self) -> Result<Mnemonic> {
+        parse_mnemonic(&self.0)
+    }
+
+    /// The raw phrase, for showing it to the user
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for MnemonicPhrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MnemonicPhrase(<redacted>)")
+    }
+}
+
+/// Root of the deterministic key hierarchy
+///
+/// Spending secrets are derived as
+/// `HMAC-SHA512(chain_code, master_key || "spend" || index)` reduced into the field,
+/// where `(master_key, chain_code)` is `HMAC-SHA512("private-token master key", seed)`.
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub struct HdWallet {
+    master_key: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+impl HdWallet {
+    /// Create the hierarchy from a mnemonic (with an empty BIP-39 passphrase)
+    pub fn from_mnemonic(mnemonic: &Mnemonic) -> Self {
+        Self::from_seed(Zeroizing::new(mnemonic.to_seed_normalized("")).as_slice())
+    }
+
+    /// Create the hierarchy from a raw BIP-39 seed
+    pub fn from_seed(seed: &[u8]) -> Self {
+        let mut mac = HmacSha512::new_from_slice(MASTER_KEY_DOMAIN)
+            .expect("HMAC accepts keys of any length");
+        mac.update(seed);
+        let output = Zeroizing::new(mac.finalize().into_bytes().to_vec());
+
+        let mut master_key = [0u8; 32];
+        let mut chain_code = [0u8; 32];
+        master_key.copy_from_slice(&output[..32]);
+        chain_code.copy_from_slice(&output[32..]);
+        Self {
+            master_key,
+            chain_code,
+        }
+    }
+
+    /// Derive the spending key for an account index
+    pub fn spending_key(&self, index: u32) -> SpendingKey {
+        let mut mac = HmacSha512::new_from_slice(&self.chain_code)
+            .expect("HMAC accepts keys of any length");
+        mac.update(&self.master_key);
+        mac.update(SPENDING_KEY_TAG);
+        mac.update(&index.to_be_bytes());
+        let output = Zeroizing::new(mac.finalize().into_bytes().to_vec());
+        SpendingKey(Field::from_bytes_reduce(&output))
+    }
+}
+
+/// Secret key that authorizes spending an account's notes
+///
+/// The key is wiped from memory when dropped and redacted from `Debug` output.
+/// `expose_secret` is the only way to reach the raw value; use it to build
+/// circuit witnesses and nowhere else that could leak it.
+#[derive(Clone, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
+pub struct SpendingKey(Field);
+
+impl SpendingKey {
+    /// Generate a new random spending key
+    pub fn generate() -> Self {
+        Self(crypto::generate_secret())
+    }
+
+    /// Wrap a raw secret
+    pub fn from_field(secret: Field) -> Self {
+        Self(secret)
+    }
+
+    /// Parse a strict 32-byte hex secret
+    pub fn from_hex(hex_str: &str) -> Result<Self> {
+        crypto::hex_to_field(hex_str).map(Self)
+    }
+
+    /// The raw secret, for building witnesses and exports
+    pub fn expose_secret(&self) -> &Field {
+        &self.0
+    }
+
+    /// Viewing key of this account
+    pub fn viewing_key(&self) -> ViewingKey {
+        ViewingKey::from_spending_key(self)
+    }
+
+    /// Address of this account
+    pub fn address(&self) -> Field {
+        crypto::derive_address(&self.0)
+    }
+}
+
+impl fmt::Debug for SpendingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SpendingKey(<redacted>)")
+    }
+}
+
+impl Serialize for SpendingKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SpendingKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Field::deserialize(deserializer).map(Self)
+    }
+}
+
+/// Deserialize an optional spending key, treating an empty string as `None`
+pub fn deserialize_optional_spending_key<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<SpendingKey>, D::Error> {
+    Ok(field::deserialize_optional(deserializer)?.map(SpendingKey))
+}
+
+/// Read-only key for an account
+///
+/// The viewing key derives the account address and every nullifier, so its holder
+/// can track balances and see when notes are spent. Its outgoing key opens the
+/// change the account sends itself. Proving a spend still requires the secret the
+/// viewing key was hashed from.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ViewingKey(Field);
+
+impl ViewingKey {
+    /// Derive the viewing key for a spending key
+    pub fn from_spending_key(spending_key: &SpendingKey) -> Self {
+        Self(crypto::derive_viewing_key(spending_key.expose_secret()))
+    }
+
+    /// Wrap an exported viewing key
+    pub fn from_field(key: Field) -> Self {
+        Self(key)
+    }
+
+    /// The raw viewing key
+    pub fn as_field(&self) -> &Field {
+        &self.0
+    }
+
+    /// Address of the account this key views
+    pub fn address(&self) -> Field {
+        crypto::address_from_viewing_key(&self.0)
+    }
+
+    /// Nullifier of the account's note with the given scheme, nonce and blinding
+    pub fn nullifier(&self, scheme: HashScheme, nonce: u64, blinding: &Field) -> Field {
+        crypto::compute_nullifier(scheme, &self.0, nonce, blinding)
+    }
+
+    /// Incoming viewing key, used to decrypt notes sent to this account
+    pub fn incoming(&self) -> Field {
+        derive_subkey(INCOMING_VIEWING_KEY_TAG, &self.0)
+    }
+
+    /// Outgoing viewing key, used to decrypt the change of notes this account sent
+    pub fn outgoing(&self) -> Field {
+        derive_subkey(OUTGOING_VIEWING_KEY_TAG, &self.0)
+    }
+
+    /// Public key senders encrypt notes to (see `note::encryption_key`)
+    pub fn encryption_key(&self) -> Field {
+        note::encryption_key(self)
+    }
+
+    /// Public key the account encrypts its own change to (see `note::change_key`)
+    pub fn change_key(&self) -> Field {
+        note::change_key(self)
+    }
+
+    /// Payment address others send this account tokens with
+    pub fn payment_address(&self) -> PaymentAddress {
+        PaymentAddress {
+            address: self.address(),
+            encryption_key: self.encryption_key(),
+        }
+    }
+}
+
+impl fmt::Display for ViewingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Debug for ViewingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ViewingKey(address: {})", self.address())
+    }
+}
+
+/// An account's address together with the key notes to it are encrypted to
+///
+/// Encoded as `ptk1` followed by the hex of the address, the encryption key and the
+/// first bytes of their SHA-256, so a key that was mistyped or paired with another
+/// account's address is rejected rather than sending notes nobody can decrypt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaymentAddress {
+    /// Address the recipient's note is committed to
+    pub address: Field,
+    /// Key the recipient's note is encrypted to
+    pub encryption_key: Field,
+}
+
+impl PaymentAddress {
+    fn checksum(&self) -> [u8; PAYMENT_ADDRESS_CHECKSUM_LEN] {
+        let digest = Sha256::new()
+            .chain_update(self.address.as_bytes())
+            .chain_update(self.encryption_key.as_bytes())
+            .finalize();
+        digest[..PAYMENT_ADDRESS_CHECKSUM_LEN]
+            .try_into()
+            .expect("SHA-256 is longer than the checksum")
+    }
+}
+
+impl fmt::Display for PaymentAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}{}",
+            PAYMENT_ADDRESS_PREFIX,
+            hex::encode(self.address.as_bytes()),
+            hex::encode(self.encryption_key.as_bytes()),
+            hex::encode(self.checksum())
+        )
+    }
+}
+
+impl FromStr for PaymentAddress {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            ClientError::InvalidInput(format!("Invalid payment address {}: {}", s, reason))
+        };
+        let encoded = s
+            .strip_prefix(PAYMENT_ADDRESS_PREFIX)
+            .ok_or_else(|| invalid(&format!("expected it to start with {}", PAYMENT_ADDRESS_PREFIX)))?;
+        let bytes = hex::decode(encoded).map_err(|e| invalid(&e.to_string()))?;
+        if bytes.len() != 64 + PAYMENT_ADDRESS_CHECKSUM_LEN {
+            return Err(invalid("wrong length"));
+        }
+
+        let field = |bytes: &[u8]| {
+            Field::from_bytes(bytes.try_into().expect("slice is 32 bytes"))
+                .map_err(|e| invalid(&e.to_string()))
+        };
+        let payment = PaymentAddress {
+            address: field(&bytes[..32])?,
+            encryption_key: field(&bytes[32..64])?,
+        };
+        if payment.checksum()[..] != bytes[64..] {
+            return Err(invalid("checksum mismatch"));
+        }
+        Ok(payment)
+    }
+}
+
+/// Derive a tagged subkey of a field element
+fn derive_subkey(tag: &[u8], key: &Field) -> Field {
+    let mut mac = HmacSha512::new_from_slice(tag).expect("HMAC accepts keys of any length");
+    mac.update(key.as_bytes());
+    Field::from_bytes_reduce(&mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon \
+                                 abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn test_generate_mnemonic_roundtrip() {
+        let mnemonic = generate_mnemonic();
+        assert_eq!(mnemonic.word_count(), 24);
+        let parsed = parse_mnemonic(&mnemonic.to_string()).unwrap();
+        assert_eq!(parsed, mnemonic);
+    }
+
+    #[test]
+    fn test_parse_normalizes_input() {
+        let messy = format!("  {}  ", TEST_MNEMONIC.to_uppercase().replace(' ', "   "));
+        assert_eq!(
+            parse_mnemonic(&messy).unwrap(),
+            parse_mnemonic(TEST_MNEMONIC).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_checksum() {
+        let phrase = TEST_MNEMONIC.replace("about", "abandon");
+        assert!(parse_mnemonic(&phrase).is_err());
+    }
+
+    #[test]
+    fn test_derivation_is_deterministic() {
+        let mnemonic = parse_mnemonic(TEST_MNEMONIC).unwrap();
+        let wallet1 = HdWallet::from_mnemonic(&mnemonic);
+        let wallet2 = HdWallet::from_mnemonic(&mnemonic);
+        assert_eq!(wallet1.spending_key(0), wallet2.spending_key(0));
+        assert_ne!(wallet1.spending_key(0), wallet1.spending_key(1));
+    }
+
+    #[test]
+    fn test_spending_key_vector() {
+        let wallet = HdWallet::from_mnemonic(&parse_mnemonic(TEST_MNEMONIC).unwrap());
+        assert_eq!(
+            wallet.spending_key(0).expose_secret().to_hex(),
+            "0x016f2fb97f3c0fbaa83635d8ad0ad1e3577dca36da79c062a90313798ea8e7b7"
+        );
+    }
+
+    #[test]
+    fn test_viewing_key_matches_spending_key() {
+        let secret = SpendingKey::generate();
+        let viewing_key = secret.viewing_key();
+        assert_eq!(viewing_key.address(), secret.address());
+        let raw_key = crypto::derive_viewing_key(secret.expose_secret());
+        let blinding = crypto::generate_blinding();
+        for scheme in HashScheme::ALL {
+            assert_eq!(
+                viewing_key.nullifier(scheme, 7, &blinding),
+                crypto::compute_nullifier(scheme, &raw_key, 7, &blinding)
+            );
+        }
+    }
+
+    #[test]
+    fn test_spending_key_is_redacted() {
+        let secret = SpendingKey::generate();
+        let debug = format!("{:?}", secret);
+        assert_eq!(debug, "SpendingKey(<redacted>)");
+        assert!(!debug.contains(&secret.expose_secret().to_hex()[2..]));
+    }
+
+    #[test]
+    fn test_mnemonic_phrase_is_redacted() {
+        let phrase = MnemonicPhrase::new(TEST_MNEMONIC.to_string());
+        assert_eq!(format!("{:?}", phrase), "MnemonicPhrase(<redacted>)");
+        assert_eq!(phrase.to_mnemonic().unwrap(), parse_mnemonic(TEST_MNEMONIC).unwrap());
+
+        let json = serde_json::to_string(&phrase).unwrap();
+        assert_eq!(serde_json::from_str::<MnemonicPhrase>(&json).unwrap(), phrase);
+    }
+
+    #[test]
+    fn test_spending_key_zeroize() {
+        let mut secret = SpendingKey::from_field(Field::from(42u64));
+        secret.zeroize();
+        assert!(secret.expose_secret().is_zero());
+    }
+
+    #[test]
+    fn test_spending_key_serde_roundtrip() {
+        let secret = SpendingKey::generate();
+        let json = serde_json::to_string(&secret).unwrap();
+        assert_eq!(json, format!("\"{}\"", secret.expose_secret()));
+        assert_eq!(serde_json::from_str::<SpendingKey>(&json).unwrap(), secret);
+    }
+
+    #[test]
+    fn test_viewing_subkeys_are_distinct() {
+        let viewing_key = SpendingKey::generate().viewing_key();
+        assert_ne!(viewing_key.incoming(), viewing_key.outgoing());
+        assert_ne!(viewing_key.incoming(), *viewing_key.as_field());
+        assert_ne!(viewing_key.encryption_key(), viewing_key.change_key());
+    }
+
+    #[test]
+    fn test_payment_address_roundtrip() {
+        let payment = SpendingKey::generate().viewing_key().payment_address();
+        let encoded = payment.to_string();
+        assert!(encoded.starts_with(PAYMENT_ADDRESS_PREFIX));
+        assert_eq!(encoded.parse::<PaymentAddress>().unwrap(), payment);
+
+        // Pairing the address with another account's key breaks the checksum
+        let other_key = SpendingKey::generate().viewing_key().encryption_key();
+        let swapped = encoded.replace(
+            &hex::encode(payment.encryption_key.as_bytes()),
+            &hex::encode(other_key.as_bytes()),
+        );
+        assert!(swapped.parse::<PaymentAddress>().is_err());
+
+        assert!(encoded[..encoded.len() - 2].parse::<PaymentAddress>().is_err());
+        assert!(encoded.replacen(PAYMENT_ADDRESS_PREFIX, "0x", 1).parse::<PaymentAddress>().is_err());
+    }
+}
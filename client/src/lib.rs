@@ -7,9 +7,21 @@ pub mod prover;
 pub mod contract;
 pub mod crypto;
 pub mod error;
+pub mod field;
+pub mod keys;
+pub mod note;
+pub mod vault;
+pub mod store;
+pub mod sqlite;
+pub mod migrate;
+pub mod history;
+pub mod selection;
+pub mod backup;
+pub mod bb;
 
 pub use state::StateManager;
-pub use prover::ProofGenerator;
-pub use contract::PrivateTokenContract;
+pub use prover::{ProofGenerator, ProverBackend};
+pub use contract::{ContractConfig, PrivateTokenContract, TxStatus};
 pub use crypto::*;
 pub use error::ClientError;
+pub use field::Field;
@@ -2,13 +2,22 @@
 //!
 //! A command-line interface for privacy-preserving token operations.
 
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use std::io::{IsTerminal, Read};
 use tracing_subscriber::{fmt, EnvFilter};
+use zeroize::Zeroizing;
 
 use private_token_client::{
-    crypto, ContractConfig, PrivateTokenContract, ProofGenerator, StateManager,
-    prover::{MintInputs, TransferInputs},
-    state::Commitment,
+    crypto::{self, HashScheme}, ContractConfig, PrivateTokenContract, ProofGenerator, StateManager,
+    TxStatus,
+    backup,
+    history::{self, HistoryEntry, OperationKind, OperationStatus},
+    keys::{self, MnemonicPhrase, SpendingKey, ViewingKey},
+    note::{self, EncryptedNote, NotePlaintext},
+    prover::{CircuitKind, MintInputs, TransferInputs},
+    selection::CoinSelection,
+    state::{Commitment, NoteStatus},
+    store,
 };
 
 #[derive(Parser)]
@@ -32,9 +41,35 @@ struct Cli {
 enum Commands {
     /// Generate a new account (secret key)
     NewAccount {
-        /// Optional name/label for the account
+        /// Unique label for the account, usable wherever an address is
         #[arg(long)]
         name: Option<String>,
+
+        /// Derive the account from the wallet mnemonic (generated on first use)
+        #[arg(long)]
+        from_mnemonic: bool,
+    },
+
+    /// Restore HD accounts from a mnemonic phrase read from stdin, or merge in a backup
+    Restore {
+        /// Read the mnemonic phrase from an open file descriptor instead of stdin
+        #[arg(long)]
+        mnemonic_fd: Option<u32>,
+
+        /// Number of accounts to derive
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+
+        /// Merge a backup made with `backup` into this wallet instead
+        #[arg(long, conflicts_with_all = ["mnemonic_fd", "count"])]
+        backup: Option<String>,
+    },
+
+    /// Write an encrypted backup of all accounts, notes, history and settings
+    Backup {
+        /// File to write the backup to
+        #[arg(long)]
+        output: String,
     },
 
     /// List all accounts and balances
@@ -42,35 +77,87 @@ enum Commands {
 
     /// Check balance for an account
     Balance {
-        /// Address (hex) or account name
+        /// Account label or address (hex)
         #[arg(long)]
         address: String,
     },
 
+    /// Label an account, or change its label
+    RenameAccount {
+        /// Account label or address (hex)
+        #[arg(long)]
+        account: String,
+
+        /// New label
+        #[arg(long)]
+        name: String,
+    },
+
+    /// Remove an account's label
+    DeleteLabel {
+        /// Label to remove
+        #[arg(long)]
+        name: String,
+    },
+
     /// Mint tokens to an address
+    #[command(group(ArgGroup::new("recipient").required(true).args(["to", "secret_stdin", "secret_fd"])))]
     Mint {
-        /// Recipient secret (hex)
+        /// Recipient account in this wallet (label or address)
         #[arg(long)]
-        secret: String,
+        to: Option<String>,
+
+        #[command(flatten)]
+        secret: SecretSource,
 
         /// Amount to mint
         #[arg(long)]
         amount: u128,
+
+        /// Hash scheme for the new note (pedersen or poseidon2)
+        #[arg(long, default_value_t = HashScheme::Pedersen)]
+        hash: HashScheme,
     },
 
     /// Transfer tokens privately
+    #[command(group(ArgGroup::new("sender").required(true).args(["from", "secret_stdin", "secret_fd"])))]
     Transfer {
-        /// Sender secret (hex)
+        /// Sending account in this wallet (label or address)
         #[arg(long)]
-        from_secret: String,
+        from: Option<String>,
 
-        /// Recipient address (hex)
-        #[arg(long)]
-        to_address: String,
+        #[command(flatten)]
+        secret: SecretSource,
+
+        /// Recipient payment address, or the label or address of an account in this wallet
+        #[arg(long, alias = "to-address")]
+        to: String,
 
         /// Amount to transfer
         #[arg(long)]
         amount: u128,
+
+        /// Which note to spend (largest-first, smallest-sufficient or privacy)
+        #[arg(long, default_value_t = CoinSelection::default())]
+        selection: CoinSelection,
+    },
+
+    /// Discover incoming notes by trial-decrypting on-chain transfers
+    Sync {
+        /// Block to start scanning from
+        #[arg(long, default_value_t = 0)]
+        from_block: u64,
+    },
+
+    /// Show past mints and transfers
+    History {
+        /// Only show operations of this account (label or address)
+        #[arg(long)]
+        address: Option<String>,
+
+        /// Print the history as JSON
+        #[arg(long)]
+        json: bool,
     },
 
     /// Show commitment details
@@ -82,12 +169,77 @@ enum Commands {
 
     /// Export account info
     Export {
-        /// Address to export
+        /// Account to export (label or address)
         #[arg(long)]
         address: String,
     },
+
+    /// Export the viewing key of an account (grants read-only access)
+    ExportViewingKey {
+        /// Account to export (label or address)
+        #[arg(long)]
+        address: String,
+    },
+
+    /// Import a viewing key (hex), read from stdin, as a view-only account
+    ImportViewingKey {
+        /// Read the viewing key from an open file descriptor instead of stdin
+        #[arg(long)]
+        key_fd: Option<u32>,
+    },
+
+    /// Re-encrypt the wallet under a new password
+    ChangePassword,
+
+    /// Upgrade the state file to the current format
+    Migrate {
+        /// Report what would change without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Manage circuit verification keys
+    Vk {
+        #[command(subcommand)]
+        command: VkCommand,
+    },
 }
 
+#[derive(Subcommand)]
+enum VkCommand {
+    /// Print each circuit's verification key hash, and optionally write the keys out
+    Export {
+        /// Hash scheme of the circuits (pedersen or poseidon2)
+        #[arg(long, default_value_t = HashScheme::Pedersen)]
+        hash: HashScheme,
+
+        /// Also write each key to this directory, e.g. to generate verifiers from
+        #[arg(long)]
+        output: Option<String>,
+    },
+}
+
+/// A spending key that isn't stored in the wallet
+///
+/// Secrets are never taken as arguments, which would leave them in shell history
+/// and `ps` output.
+#[derive(Args)]
+struct SecretSource {
+    /// Read the secret (hex) from stdin
+    #[arg(long)]
+    secret_stdin: bool,
+
+    /// Read the secret (hex) from an open file descriptor
+    #[arg(long, conflicts_with = "secret_stdin")]
+    secret_fd: Option<u32>,
+}
+
+/// Environment variable that supplies the wallet password non-interactively
+const PASSWORD_ENV: &str = "PRIVATE_TOKEN_PASSWORD";
+
+/// Environment variable that supplies the backup password non-interactively
+const BACKUP_PASSWORD_ENV: &str = "PRIVATE_TOKEN_BACKUP_PASSWORD";
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize logging
@@ -100,12 +252,48 @@ async fn main() -> anyhow::Result<()> {
 
     let cli = Cli::parse();
 
-    // Initialize state manager
-    let mut state = StateManager::new(&cli.state_file)?;
+    // Opening the wallet migrates it, so this has to run first
+    if let Commands::Migrate { dry_run } = cli.command {
+        if !StateManager::exists(&cli.state_file) {
+            anyhow::bail!("No wallet found at {}", cli.state_file);
+        }
+        let password = read_password(&cli.state_file)?;
+        return migrate_state(&cli.state_file, &password, dry_run);
+    }
+
+    // Verification keys don't need the wallet
+    if let Commands::Vk {
+        command: VkCommand::Export { hash, output },
+    } = &cli.command
+    {
+        return export_verification_keys(&cli.circuits_dir, *hash, output.as_deref());
+    }
+
+    // Unlock the wallet, writing it out straight away if it's new
+    let password = read_password(&cli.state_file)?;
+    let is_new = !StateManager::exists(&cli.state_file);
+    let mut state = StateManager::new(&cli.state_file, &password)?;
+    if is_new {
+        state.save()?;
+    }
 
     match cli.command {
-        Commands::NewAccount { name } => {
-            new_account(&mut state, name)?;
+        Commands::NewAccount { name, from_mnemonic } => {
+            new_account(&mut state, name, from_mnemonic)?;
+        }
+        Commands::Restore {
+            backup: Some(backup),
+            ..
+        } => {
+            restore_backup(&mut state, &backup)?;
+        }
+        Commands::Restore {
+            mnemonic_fd, count, ..
+        } => {
+            restore_accounts(&mut state, mnemonic_fd, count)?;
+        }
+        Commands::Backup { output } => {
+            backup_wallet(&state, &output)?;
         }
         Commands::Accounts => {
             list_accounts(&state)?;
@@ -113,16 +301,44 @@ async fn main() -> anyhow::Result<()> {
         Commands::Balance { address } => {
             show_balance(&state, &address)?;
         }
-        Commands::Mint { secret, amount } => {
-            mint_tokens(&mut state, &cli.circuits_dir, &secret, amount).await?;
+        Commands::RenameAccount { account, name } => {
+            rename_account(&mut state, &account, &name)?;
+        }
+        Commands::DeleteLabel { name } => {
+            delete_label(&mut state, &name)?;
+        }
+        Commands::Mint {
+            to,
+            secret,
+            amount,
+            hash,
+        } => {
+            let secret = account_secret(&state, to.as_deref(), &secret)?;
+            mint_tokens(&mut state, &cli.circuits_dir, secret, amount, hash).await?;
         }
         Commands::Transfer {
-            from_secret,
-            to_address,
+            from,
+            secret,
+            to,
             amount,
+            selection,
         } => {
-            transfer_tokens(&mut state, &cli.circuits_dir, &from_secret, &to_address, amount)
-                .await?;
+            let from_secret = account_secret(&state, from.as_deref(), &secret)?;
+            transfer_tokens(
+                &mut state,
+                &cli.circuits_dir,
+                from_secret,
+                &to,
+                amount,
+                selection,
+            )
+            .await?;
+        }
+        Commands::Sync { from_block } => {
+            sync_notes(&mut state, from_block).await?;
+        }
+        Commands::History { address, json } => {
+            show_history(&state, address.as_deref(), json)?;
         }
         Commands::ShowCommitment { commitment } => {
             show_commitment(&state, &commitment)?;
@@ -130,23 +346,156 @@ async fn main() -> anyhow::Result<()> {
         Commands::Export { address } => {
             export_account(&state, &address)?;
         }
+        Commands::ExportViewingKey { address } => {
+            export_viewing_key(&state, &address)?;
+        }
+        Commands::ImportViewingKey { key_fd } => {
+            import_viewing_key(&mut state, key_fd)?;
+        }
+        Commands::ChangePassword => {
+            change_password(&mut state)?;
+        }
+        Commands::Migrate { .. } | Commands::Vk { .. } => {
+            unreachable!("handled before the wallet is opened")
+        }
     }
 
     Ok(())
 }
 
-fn new_account(state: &mut StateManager, name: Option<String>) -> anyhow::Result<()> {
-    let secret = crypto::generate_secret();
-    let address = crypto::derive_address(&secret);
+/// Read the wallet password from the environment or the terminal
+///
+/// A new wallet, or an unencrypted one from an older client, asks for the password
+/// twice.
+fn read_password(state_file: &str) -> anyhow::Result<Zeroizing<String>> {
+    if let Ok(password) = std::env::var(PASSWORD_ENV) {
+        return Ok(Zeroizing::new(password));
+    }
+    if StateManager::is_unencrypted(state_file) {
+        println!("{} is not encrypted yet; it will be encrypted with a new password", state_file);
+        prompt_new_password("Choose a wallet password: ")
+    } else if StateManager::exists(state_file) {
+        Ok(Zeroizing::new(rpassword::prompt_password("Wallet password: ")?))
+    } else {
+        println!("Creating a new wallet at {}", state_file);
+        prompt_new_password("Choose a wallet password: ")
+    }
+}
 
-    let secret_hex = crypto::bytes32_to_hex(&secret);
-    let address_hex = crypto::bytes32_to_hex(&address);
+/// Prompt for a new password and its confirmation
+fn prompt_new_password(prompt: &str) -> anyhow::Result<Zeroizing<String>> {
+    let password = Zeroizing::new(rpassword::prompt_password(prompt)?);
+    let confirmation = Zeroizing::new(rpassword::prompt_password("Confirm password: ")?);
+    if password.is_empty() {
+        anyhow::bail!("Password must not be empty");
+    }
+    if *password != *confirmation {
+        anyhow::bail!("Passwords do not match");
+    }
+    Ok(password)
+}
 
-    state.add_account(address_hex.clone(), secret_hex.clone())?;
+/// The spending key of a wallet account given by label or address, or else the
+/// key read from `source`
+fn account_secret(
+    state: &StateManager,
+    account: Option<&str>,
+    source: &SecretSource,
+) -> anyhow::Result<SpendingKey> {
+    if let Some(account) = account {
+        let address = state.resolve_account(account)?;
+        return state
+            .get_secret(&address)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No spending key for {} in this wallet", account));
+    }
+
+    if !source.secret_stdin && source.secret_fd.is_none() {
+        anyhow::bail!("Pass an account, --secret-stdin or --secret-fd");
+    }
+    let secret_hex = read_secret("Secret: ", "--secret-fd", source.secret_fd)?;
+    Ok(SpendingKey::from_hex(secret_hex.trim())?)
+}
+
+/// Read a secret from the file descriptor passed as `flag`, or else from stdin,
+/// prompting for it without echo on a terminal
+fn read_secret(prompt: &str, flag: &str, fd: Option<u32>) -> anyhow::Result<Zeroizing<String>> {
+    let mut secret = Zeroizing::new(String::new());
+    if let Some(fd) = fd {
+        read_fd(fd, flag, &mut secret)?;
+    } else if std::io::stdin().is_terminal() {
+        secret = Zeroizing::new(rpassword::prompt_password(prompt)?);
+    } else {
+        std::io::stdin().read_line(&mut secret)?;
+    }
+    Ok(secret)
+}
+
+/// Read everything from an inherited file descriptor, e.g. `--secret-fd 3 3<secret.txt`
+#[cfg(unix)]
+fn read_fd(fd: u32, flag: &str, buf: &mut String) -> anyhow::Result<()> {
+    if fd <= 2 {
+        anyhow::bail!("{} must not be stdin, stdout or stderr; pipe the secret to stdin", flag);
+    }
+    let path = format!("/dev/fd/{}", fd);
+    std::fs::File::open(&path)
+        .map_err(|e| anyhow::anyhow!("Cannot read file descriptor {}: {}", fd, e))?
+        .read_to_string(buf)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: u32, flag: &str, _buf: &mut String) -> anyhow::Result<()> {
+    anyhow::bail!("{} is only supported on Unix; pipe the secret to stdin", flag)
+}
+
+fn new_account(
+    state: &mut StateManager,
+    name: Option<String>,
+    from_mnemonic: bool,
+) -> anyhow::Result<()> {
+    if let Some(name) = &name {
+        state.check_label(name)?;
+    }
+
+    let (secret, index) = if from_mnemonic {
+        let mnemonic = match state.get_mnemonic() {
+            Some(phrase) => phrase.to_mnemonic()?,
+            None => {
+                let mnemonic = keys::generate_mnemonic();
+                state.set_mnemonic(MnemonicPhrase::from_mnemonic(&mnemonic))?;
+
+                println!("🔑 New wallet mnemonic generated:");
+                println!("   {}", mnemonic);
+                println!();
+                println!("⚠️  Write this phrase down! It restores every account in this wallet.");
+                println!();
+                mnemonic
+            }
+        };
+        let index = state.next_account_index();
+        let secret = keys::HdWallet::from_mnemonic(&mnemonic).spending_key(index);
+        (secret, Some(index))
+    } else {
+        (SpendingKey::generate(), None)
+    };
+    let address = secret.address();
+
+    match index {
+        Some(index) => state.add_derived_account(index, address, secret.clone())?,
+        None => state.add_account(address, secret.clone())?,
+    }
+    if let Some(name) = &name {
+        state.set_label(&address, name)?;
+    }
 
     println!("✅ New account created!");
-    println!("   Address: {}", address_hex);
-    println!("   Secret:  {}", secret_hex);
+    println!("   Address: {}", address);
+    println!("   Payment address: {}", secret.viewing_key().payment_address());
+    println!("   Secret:  {}", secret.expose_secret());
+    if let Some(i) = index {
+        println!("   Index:   {}", i);
+    }
     if let Some(n) = name {
         println!("   Name:    {}", n);
     }
@@ -157,8 +506,73 @@ fn new_account(state: &mut StateManager, name: Option<String>) -> anyhow::Result
     Ok(())
 }
 
+fn restore_accounts(
+    state: &mut StateManager,
+    mnemonic_fd: Option<u32>,
+    count: u32,
+) -> anyhow::Result<()> {
+    let phrase = read_secret("Mnemonic phrase: ", "--mnemonic-fd", mnemonic_fd)?;
+    let mnemonic = keys::parse_mnemonic(&phrase)?;
+    state.set_mnemonic(MnemonicPhrase::from_mnemonic(&mnemonic))?;
+    let wallet = keys::HdWallet::from_mnemonic(&mnemonic);
+
+    println!("✅ Restored {} account(s):", count);
+    for index in 0..count {
+        let secret = wallet.spending_key(index);
+        let address = secret.address();
+        state.add_derived_account(index, address, secret)?;
+        println!("   [{}] {}", index, address);
+    }
+
+    Ok(())
+}
+
+fn backup_wallet(state: &StateManager, output: &str) -> anyhow::Result<()> {
+    let password = match std::env::var(BACKUP_PASSWORD_ENV) {
+        Ok(password) => Zeroizing::new(password),
+        Err(_) => prompt_new_password("Choose a backup password: ")?,
+    };
+    let snapshot = state.backup()?;
+    backup::write(output, &snapshot, &password)?;
+
+    println!("✅ Backup written to {}", output);
+    println!("   Accounts: {}", snapshot.keyring.accounts.len() + snapshot.keyring.view_only.len());
+    println!("   Notes:    {}", snapshot.commitments.len());
+    println!("   History:  {}", snapshot.history.len());
+    println!();
+    println!("⚠️  The backup holds every secret key. Keep it and its password safe.");
+
+    Ok(())
+}
+
+fn restore_backup(state: &mut StateManager, path: &str) -> anyhow::Result<()> {
+    let password = match std::env::var(BACKUP_PASSWORD_ENV) {
+        Ok(password) => Zeroizing::new(password),
+        Err(_) => Zeroizing::new(rpassword::prompt_password("Backup password: ")?),
+    };
+    let snapshot = backup::read(path, &password)?;
+    let report = state.restore_backup(snapshot)?;
+
+    println!("✅ Restored backup from {}", path);
+    println!("   Accounts added:     {}", report.accounts_added);
+    println!("   Notes added:        {}", report.notes_added);
+    println!("   Notes marked spent: {}", report.notes_spent);
+    println!("   History added:      {}", report.history_added);
+    if report.notes_kept > 0 {
+        println!(
+            "   {} note(s) are in a different state in the backup and were left as they are",
+            report.notes_kept
+        );
+    }
+    for label in &report.labels_skipped {
+        println!("   Label {} was not restored: it is already in use here", label);
+    }
+
+    Ok(())
+}
+
 fn list_accounts(state: &StateManager) -> anyhow::Result<()> {
-    let accounts = state.list_accounts();
+    let accounts = state.list_accounts()?;
 
     if accounts.is_empty() {
         println!("No accounts found. Create one with: private-token new-account");
@@ -168,7 +582,17 @@ fn list_accounts(state: &StateManager) -> anyhow::Result<()> {
     println!("Accounts:");
     println!("{:-<60}", "");
     for (address, balance) in accounts {
-        println!("Address: {}", address);
+        if let Some(label) = state.get_label(&address) {
+            println!("Label: {}", label);
+        }
+        if state.is_view_only(&address) {
+            println!("Address: {} (view-only)", address);
+        } else {
+            println!("Address: {}", address);
+        }
+        if let Some(viewing_key) = state.get_viewing_key(&address) {
+            println!("Payment address: {}", viewing_key.payment_address());
+        }
         println!("Balance: {} tokens", balance);
         println!("{:-<60}", "");
     }
@@ -176,13 +600,80 @@ fn list_accounts(state: &StateManager) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn show_balance(state: &StateManager, address: &str) -> anyhow::Result<()> {
-    let balance = state.get_balance(address);
-    let unspent = state.get_unspent_commitments(address);
+fn show_balance(state: &StateManager, account: &str) -> anyhow::Result<()> {
+    let address = state.resolve_account(account)?;
+    let balance = state.get_balance(&address)?;
+    let unspent = state.get_unspent_commitments(&address)?;
+    let incoming: u128 = state
+        .get_commitments_with_status(&address, NoteStatus::PendingOutput)?
+        .iter()
+        .map(|c| c.balance)
+        .sum();
 
+    if let Some(label) = state.get_label(&address) {
+        println!("Label: {}", label);
+    }
     println!("Address: {}", address);
     println!("Balance: {} tokens", balance);
     println!("Unspent UTXOs: {}", unspent.len());
+    if incoming > 0 {
+        println!("Pending: {} tokens", incoming);
+    }
+
+    Ok(())
+}
+
+fn rename_account(state: &mut StateManager, account: &str, name: &str) -> anyhow::Result<()> {
+    let address = state.resolve_account(account)?;
+    let old = state.get_label(&address).map(str::to_string);
+    state.set_label(&address, name)?;
+
+    match old {
+        Some(old) if old != name => println!("✅ Renamed {} to {}", old, name),
+        _ => println!("✅ Labelled {} as {}", address, name),
+    }
+    Ok(())
+}
+
+fn delete_label(state: &mut StateManager, name: &str) -> anyhow::Result<()> {
+    let address = state.remove_label(name)?;
+    println!("✅ Removed label {} from {}", name, address);
+    Ok(())
+}
+
+/// Path of the compiled circuit for a hash scheme
+fn circuit_path(circuits_dir: &str, circuit: &str, scheme: HashScheme) -> String {
+    let package = scheme.circuit_package(circuit);
+    format!("{}/{}/target/{}.json", circuits_dir, package, package)
+}
+
+fn export_verification_keys(
+    circuits_dir: &str,
+    scheme: HashScheme,
+    output: Option<&str>,
+) -> anyhow::Result<()> {
+    let prover = ProofGenerator::new(
+        &circuit_path(circuits_dir, "private_transfer", scheme),
+        &circuit_path(circuits_dir, "mint", scheme),
+    )?;
+
+    for (kind, circuit_name) in [
+        (CircuitKind::Mint, "mint"),
+        (CircuitKind::Transfer, "private_transfer"),
+    ] {
+        let circuit = prover.circuit(kind);
+        let vk = prover.verification_key(kind)?;
+        println!("{} circuit: {}", kind, circuit.path());
+        println!("   Bytecode hash: 0x{}", hex::encode(circuit.bytecode_hash()));
+        println!("   VK hash:       {}", vk.hash_hex());
+        if let Some(dir) = output {
+            let path = format!("{}/{}.vk", dir, scheme.circuit_package(circuit_name));
+            std::fs::write(&path, &vk.key)?;
+            println!("   Written to:    {}", path);
+        }
+    }
+    println!();
+    println!("The deployed verifier for each circuit must be generated from its key.");
 
     Ok(())
 }
@@ -190,15 +681,13 @@ fn show_balance(state: &StateManager, address: &str) -> anyhow::Result<()> {
 async fn mint_tokens(
     state: &mut StateManager,
     circuits_dir: &str,
-    secret_hex: &str,
+    secret: SpendingKey,
     amount: u128,
+    scheme: HashScheme,
 ) -> anyhow::Result<()> {
-    println!("🔒 Minting {} tokens privately...", amount);
+    println!("🔒 Minting {} tokens privately ({})...", amount, scheme);
 
-    // Parse secret
-    let secret = crypto::hex_to_bytes32(secret_hex)?;
-    let address = crypto::derive_address(&secret);
-    let address_hex = crypto::bytes32_to_hex(&address);
+    let address = secret.address();
 
     // Generate nonce (use timestamp for simplicity)
     let nonce = std::time::SystemTime::now()
@@ -206,17 +695,18 @@ async fn mint_tokens(
         .as_secs();
 
     // Compute commitment
-    let output_commitment = crypto::compute_commitment(&address, amount, nonce);
-    let output_commitment_hex = crypto::bytes32_to_hex(&output_commitment);
+    let blinding = crypto::generate_blinding();
+    let output_commitment =
+        crypto::compute_commitment(scheme, &address, amount, nonce, &blinding);
 
     // Create proof generator
-    let transfer_path = format!("{}/private_transfer/target/private_transfer.json", circuits_dir);
-    let mint_path = format!("{}/mint/target/mint.json", circuits_dir);
+    let transfer_path = circuit_path(circuits_dir, "private_transfer", scheme);
+    let mint_path = circuit_path(circuits_dir, "mint", scheme);
     
     // Check if circuits are compiled
     if !std::path::Path::new(&mint_path).exists() {
         println!("⚠️  Mint circuit not compiled. Run:");
-        println!("   cd circuits/mint && nargo compile");
+        println!("   cd circuits/{} && nargo compile", scheme.circuit_package("mint"));
         println!();
         println!("Simulating mint for demo purposes...");
     }
@@ -226,23 +716,32 @@ async fn mint_tokens(
     // In production, generate actual proof here
     // let prover = ProofGenerator::new(&transfer_path, &mint_path)?;
     // let proof = prover.generate_mint_proof(MintInputs { ... })?;
-    // let contract = PrivateTokenContract::from_env()?;
-    // let tx_hash = contract.mint(proof).await?;
+    // let contract = PrivateTokenContract::from_env()?.with_verifier(prover);
+    // let tx_hash = contract.mint(proof).await?; // verifies the proof locally first
 
-    // For demo, just update local state
+    // For demo, record the mint as if it had been submitted and mined straight away
     let commitment = Commitment {
-        commitment: output_commitment_hex.clone(),
-        address: address_hex.clone(),
+        commitment: output_commitment,
+        address,
         balance: amount,
         nonce,
-        secret: secret_hex.to_string(),
-        spent: false,
+        blinding,
+        scheme,
+        secret: Some(secret),
+        status: NoteStatus::PendingOutput,
+        created_by: None,
+        spent_by: None,
+        block_number: None,
     };
-    state.add_commitment(commitment)?;
+    let mut entry = HistoryEntry::new(OperationKind::Mint, output_commitment, address, amount);
+    entry.created = vec![output_commitment];
+    let tx_hash = demo_tx_hash();
+    state.record_transaction(&tx_hash, &[], vec![commitment], vec![entry])?;
+    state.confirm_transaction(&tx_hash, None)?;
 
     println!("✅ Minted {} tokens", amount);
-    println!("   Address: {}", address_hex);
-    println!("   Commitment: {}", output_commitment_hex);
+    println!("   Address: {}", address);
+    println!("   Commitment: {}", output_commitment);
     println!();
     println!("Note: In production, this would submit a ZK proof to the blockchain.");
 
@@ -252,81 +751,281 @@ async fn mint_tokens(
 async fn transfer_tokens(
     state: &mut StateManager,
     circuits_dir: &str,
-    from_secret_hex: &str,
-    to_address_hex: &str,
+    sender_secret: SpendingKey,
+    to_account: &str,
     amount: u128,
+    selection: CoinSelection,
 ) -> anyhow::Result<()> {
     println!("🔒 Transferring {} tokens privately...", amount);
 
     // Parse inputs
-    let sender_secret = crypto::hex_to_bytes32(from_secret_hex)?;
-    let sender_address = crypto::derive_address(&sender_secret);
-    let sender_address_hex = crypto::bytes32_to_hex(&sender_address);
-    let recipient_address = crypto::hex_to_bytes32(to_address_hex)?;
+    let sender_address = sender_secret.address();
+    let recipient = state.resolve_recipient(to_account)?;
+    let recipient_address = recipient.address;
 
-    // Find spendable commitment
-    let spendable = state
-        .find_spendable_commitment(&sender_address_hex, amount)
-        .ok_or_else(|| anyhow::anyhow!("Insufficient balance"))?
-        .clone();
+    // Choose the note to spend
+    let spendable =
+        state.select_commitment(&sender_address, amount, selection.selector().as_ref())?;
 
     let sender_balance = spendable.balance;
     let new_balance = sender_balance - amount;
     let new_nonce = spendable.nonce + 1;
+    let new_blinding = crypto::generate_blinding();
+    let recipient_blinding = crypto::generate_blinding();
 
     // Compute values
-    let input_commitment = crypto::hex_to_bytes32(&spendable.commitment)?;
-    let nullifier = crypto::compute_nullifier(&sender_secret, spendable.nonce);
-    let output_commitment_sender = crypto::compute_commitment(&sender_address, new_balance, new_nonce);
-    let output_commitment_recipient = crypto::compute_commitment(&recipient_address, amount, 0);
+    let input_commitment = spendable.commitment;
+    // Outputs use the input note's scheme, since one circuit proves the whole transfer
+    let scheme = spendable.scheme;
+    let nullifier =
+        sender_secret.viewing_key().nullifier(scheme, spendable.nonce, &spendable.blinding);
+    let output_commitment_sender =
+        crypto::compute_commitment(scheme, &sender_address, new_balance, new_nonce, &new_blinding);
+    let output_commitment_recipient =
+        crypto::compute_commitment(scheme, &recipient_address, amount, 0, &recipient_blinding);
 
-    // In production, generate proof and submit to blockchain
-    // For demo, just update local state
+    // Encrypt the recipient's note so they can discover it on-chain
+    let recipient_note = EncryptedNote {
+        commitment: output_commitment_recipient,
+        ciphertext: note::encrypt_note(
+            &recipient.encryption_key,
+            &output_commitment_recipient,
+            &NotePlaintext {
+                amount,
+                nonce: 0,
+                blinding: recipient_blinding,
+            },
+        )?,
+        block_number: None,
+    };
+    // And the change to our outgoing key, so any holder of our viewing key can
+    // follow the balance. It's published even when empty so every transfer looks
+    // the same on-chain.
+    let change_note = note::encrypt_note(
+        &sender_secret.viewing_key().change_key(),
+        &output_commitment_sender,
+        &NotePlaintext {
+            amount: new_balance,
+            nonce: new_nonce,
+            blinding: new_blinding,
+        },
+    )?;
+    let encrypted_notes = note::encode_transfer_notes(&recipient_note.ciphertext, &change_note);
 
-    // Mark old commitment as spent
-    state.mark_spent(&spendable.commitment)?;
+    // In production, generate proof and submit to blockchain
+    // For demo, record the transfer as if it had been submitted and mined straight away
 
-    // Add new sender commitment if there's change
+    // The sender's change, plus the recipient's note if it's addressed to us
+    let mut outputs = Vec::new();
     if new_balance > 0 {
-        state.add_commitment(Commitment {
-            commitment: crypto::bytes32_to_hex(&output_commitment_sender),
-            address: sender_address_hex.clone(),
+        outputs.push(Commitment {
+            commitment: output_commitment_sender,
+            address: sender_address,
             balance: new_balance,
             nonce: new_nonce,
-            secret: from_secret_hex.to_string(),
-            spent: false,
-        })?;
+            blinding: new_blinding,
+            scheme,
+            secret: Some(sender_secret),
+            status: NoteStatus::PendingOutput,
+            created_by: None,
+            spent_by: None,
+            block_number: None,
+        });
     }
+    let received = state.decrypt_notes(&[recipient_note]);
 
-    // Add recipient commitment (they would need to import this)
-    state.add_commitment(Commitment {
-        commitment: crypto::bytes32_to_hex(&output_commitment_recipient),
-        address: to_address_hex.to_string(),
-        balance: amount,
-        nonce: 0,
-        secret: String::new(), // Recipient needs their own secret
-        spent: false,
-    })?;
+    let mut sent = HistoryEntry::new(
+        OperationKind::Send,
+        output_commitment_recipient,
+        sender_address,
+        amount,
+    );
+    sent.counterparty = Some(recipient_address);
+    sent.nullifier = Some(nullifier);
+    sent.consumed = vec![input_commitment];
+    sent.created = outputs
+        .iter()
+        .map(|c| c.commitment)
+        .chain([output_commitment_recipient])
+        .collect();
+    let mut history = vec![sent];
+    for c in &received {
+        let mut entry = HistoryEntry::new(OperationKind::Receive, c.commitment, c.address, c.balance);
+        entry.counterparty = Some(sender_address);
+        entry.created = vec![c.commitment];
+        history.push(entry);
+    }
+    outputs.extend(received);
+
+    // The input stays locked until the transaction confirms or fails
+    let tx_hash = demo_tx_hash();
+    state.record_transaction(&tx_hash, &[input_commitment], outputs, history)?;
+    state.confirm_transaction(&tx_hash, None)?;
 
     println!("✅ Transferred {} tokens", amount);
-    println!("   From: {}", sender_address_hex);
-    println!("   To: {}", to_address_hex);
-    println!("   Nullifier: {}", crypto::bytes32_to_hex(&nullifier));
+    println!("   From: {}", sender_address);
+    println!("   To: {}", recipient_address);
+    println!("   Nullifier: {}", nullifier);
+    println!("   Encrypted notes: {} bytes", encrypted_notes.len());
     println!();
     println!("Note: In production, this would submit a ZK proof to the blockchain.");
 
     Ok(())
 }
 
+async fn sync_notes(state: &mut StateManager, from_block: u64) -> anyhow::Result<()> {
+    println!("🔍 Scanning transfers from block {}...", from_block);
+
+    let contract = PrivateTokenContract::from_env()?;
+    settle_pending_transactions(state, &contract).await?;
+
+    let notes = contract.get_encrypted_notes(from_block).await?;
+    let discovered = state.scan_encrypted_notes(&notes)?;
+
+    println!("✅ Scanned {} notes, found {} new", notes.len(), discovered.len());
+    for commitment in discovered {
+        if let Some(c) = state.get_commitment(&commitment)? {
+            println!("   {} tokens to {} ({})", c.balance, c.address, c.commitment);
+        }
+    }
+
+    // Notes spent elsewhere, e.g. by the wallet holding the secret of a view-only
+    // account, are only visible through their nullifiers
+    let mut spent = 0;
+    for (nullifier, commitment) in state.confirmed_nullifiers()? {
+        if contract.is_nullifier_used(&nullifier).await? {
+            state.mark_spent(&commitment)?;
+            spent += 1;
+        }
+    }
+    if spent > 0 {
+        println!("   {} notes were spent", spent);
+    }
+
+    Ok(())
+}
+
+/// How long a transaction the node doesn't know of is waited on before it is
+/// treated as dropped
+const DROPPED_TRANSACTION_TIMEOUT_SECS: u64 = 30 * 60;
+
+/// Settle transactions the wallet is waiting on
+///
+/// Mined transactions confirm their notes. Reverted ones, and ones the node hasn't
+/// known of for [`DROPPED_TRANSACTION_TIMEOUT_SECS`] since they were recorded, are
+/// rolled back, which frees their inputs to be spent again.
+async fn settle_pending_transactions(
+    state: &mut StateManager,
+    contract: &PrivateTokenContract,
+) -> anyhow::Result<()> {
+    for tx_hash in state.pending_transactions()? {
+        match contract.get_transaction_status(&tx_hash).await? {
+            TxStatus::Pending => println!("   {} is still pending", tx_hash),
+            TxStatus::Unknown => {
+                let waited = state
+                    .transaction_recorded_at(&tx_hash)?
+                    .map(|recorded| history::unix_time().saturating_sub(recorded));
+                if waited.is_some_and(|waited| waited < DROPPED_TRANSACTION_TIMEOUT_SECS) {
+                    println!("   {} is not known to the node yet", tx_hash);
+                } else {
+                    state.fail_transaction(&tx_hash)?;
+                    println!("   {} was dropped, its inputs are spendable again", tx_hash);
+                }
+            }
+            TxStatus::Confirmed { block_number } => {
+                state.confirm_transaction(&tx_hash, Some(block_number))?;
+                println!("   {} confirmed in block {}", tx_hash, block_number);
+            }
+            TxStatus::Failed => {
+                state.fail_transaction(&tx_hash)?;
+                println!("   {} failed, its inputs are spendable again", tx_hash);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Random stand-in for a transaction hash while transactions aren't submitted
+fn demo_tx_hash() -> String {
+    let mut hash = [0u8; 32];
+    rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut hash);
+    format!("0x{}", hex::encode(hash))
+}
+
+fn show_history(state: &StateManager, account: Option<&str>, json: bool) -> anyhow::Result<()> {
+    let address = account.map(|account| state.resolve_account(account)).transpose()?;
+    let history = state.history(address.as_ref())?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&history)?);
+        return Ok(());
+    }
+    if history.is_empty() {
+        println!("No history found.");
+        return Ok(());
+    }
+
+    println!("History:");
+    println!("{:-<60}", "");
+    let mut net: i128 = 0;
+    for entry in &history {
+        let sign = if entry.kind.is_credit() { '+' } else { '-' };
+        println!(
+            "{}  {} {}{} tokens ({})",
+            history::format_timestamp(entry.timestamp),
+            entry.kind,
+            sign,
+            entry.amount,
+            entry.status
+        );
+        match state.get_label(&entry.address) {
+            Some(label) => println!("  Account:      {} ({})", entry.address, label),
+            None => println!("  Account:      {}", entry.address),
+        }
+        if let Some(counterparty) = entry.counterparty {
+            println!("  Counterparty: {}", counterparty);
+        }
+        if let Some(nullifier) = entry.nullifier {
+            println!("  Nullifier:    {}", nullifier);
+        }
+        if let Some(tx_hash) = &entry.tx_hash {
+            println!("  Tx:           {}", tx_hash);
+        }
+        if let Some(block_number) = entry.block_number {
+            println!("  Block:        {}", block_number);
+        }
+        for commitment in &entry.consumed {
+            println!("  Spent:        {}", commitment);
+        }
+        for commitment in &entry.created {
+            println!("  Created:      {}", commitment);
+        }
+        println!("{:-<60}", "");
+
+        if entry.status == OperationStatus::Confirmed {
+            let amount = entry.amount as i128;
+            net += if entry.kind.is_credit() { amount } else { -amount };
+        }
+    }
+    println!("Net confirmed change: {:+} tokens", net);
+
+    Ok(())
+}
+
 fn show_commitment(state: &StateManager, commitment_hex: &str) -> anyhow::Result<()> {
-    match state.get_commitment(commitment_hex) {
+    let commitment = crypto::hex_to_field(commitment_hex)?;
+    match state.get_commitment(&commitment)? {
         Some(c) => {
             println!("Commitment Details:");
             println!("  Hash:    {}", c.commitment);
             println!("  Address: {}", c.address);
             println!("  Balance: {}", c.balance);
             println!("  Nonce:   {}", c.nonce);
-            println!("  Spent:   {}", c.spent);
+            println!("  Scheme:  {}", c.scheme);
+            println!("  Status:  {}", c.status);
+            if let Some(tx_hash) = c.pending_transaction() {
+                println!("  Waiting on: {}", tx_hash);
+            }
         }
         None => {
             println!("Commitment not found: {}", commitment_hex);
@@ -335,12 +1034,13 @@ fn show_commitment(state: &StateManager, commitment_hex: &str) -> anyhow::Result
     Ok(())
 }
 
-fn export_account(state: &StateManager, address: &str) -> anyhow::Result<()> {
-    match state.get_secret(address) {
+fn export_account(state: &StateManager, account: &str) -> anyhow::Result<()> {
+    let address = state.resolve_account(account)?;
+    match state.get_secret(&address) {
         Some(secret) => {
             println!("Account Export:");
             println!("  Address: {}", address);
-            println!("  Secret:  {}", secret);
+            println!("  Secret:  {}", secret.expose_secret());
             println!();
             println!("⚠️  Keep this information secure!");
         }
@@ -350,3 +1050,66 @@ fn export_account(state: &StateManager, address: &str) -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+fn export_viewing_key(state: &StateManager, account: &str) -> anyhow::Result<()> {
+    let address = state.resolve_account(account)?;
+    match state.get_viewing_key(&address) {
+        Some(viewing_key) => {
+            println!("Viewing Key Export:");
+            println!("  Address:     {}", address);
+            println!("  Viewing key: {}", viewing_key);
+            println!();
+            println!("This key reveals balances and spends, but cannot spend funds.");
+        }
+        None => {
+            println!("Account not found: {}", account);
+        }
+    }
+    Ok(())
+}
+
+fn import_viewing_key(state: &mut StateManager, key_fd: Option<u32>) -> anyhow::Result<()> {
+    let key_hex = read_secret("Viewing key: ", "--key-fd", key_fd)?;
+    let viewing_key = ViewingKey::from_field(crypto::hex_to_field(key_hex.trim())?);
+    let address = state.add_viewing_key(viewing_key)?;
+
+    println!("✅ Viewing key imported!");
+    println!("   Address: {}", address);
+    if state.is_view_only(&address) {
+        println!("   This account is view-only and cannot spend.");
+    }
+    Ok(())
+}
+
+fn change_password(state: &mut StateManager) -> anyhow::Result<()> {
+    let password = prompt_new_password("New wallet password: ")?;
+    state.change_password(&password)?;
+    println!("✅ Wallet password changed");
+    Ok(())
+}
+
+fn migrate_state(state_file: &str, password: &str, dry_run: bool) -> anyhow::Result<()> {
+    let steps = store::plan_migration(state_file, password)?;
+    let (Some(first), Some(last)) = (steps.first(), steps.last()) else {
+        println!("✅ {} is up to date", state_file);
+        return Ok(());
+    };
+
+    for step in &steps {
+        println!("Version {} -> {}: {}", step.from, step.to, step.description);
+        for change in &step.changes {
+            println!("   - {}", change);
+        }
+    }
+    println!();
+
+    if dry_run {
+        println!("Dry run: {} was not changed.", state_file);
+        return Ok(());
+    }
+
+    StateManager::new(state_file, password)?;
+    println!("✅ Migrated {} to version {}", state_file, last.to);
+    println!("   Original kept at {}", store::migration_backup_path(state_file, first.from));
+    Ok(())
+}
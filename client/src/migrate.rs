@@ -0,0 +1,247 @@
+//! Migrations for the JSON state format
+//!
+//! The decrypted state records a `version`. State from older clients is upgraded
+//! one version at a time as raw JSON before it is deserialized, so `Commitment` and
+//! `Keyring` can change without breaking existing wallets.
+
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+use crate::crypto::HashScheme;
+use crate::error::{ClientError, Result};
+use crate::field::Field;
+use crate::state::NoteStatus;
+
+/// Current version of the JSON state format
+pub const STATE_VERSION: u32 = 3;
+
+/// One step of the migration chain
+struct Migration {
+    /// Version this step upgrades from, to `from + 1`
+    from: u32,
+    /// What the step does, for reports
+    description: &'static str,
+    /// Upgrade the state in place, returning a line per change made
+    apply: fn(&mut Map<String, Value>) -> Vec<String>,
+}
+
+/// The migration chain, in order
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "record the state version and fill in fields older clients left out",
+        apply: v0_to_v1,
+    },
+    Migration {
+        from: 1,
+        description: "replace the spent flag with a note status",
+        apply: v1_to_v2,
+    },
+    Migration {
+        from: 2,
+        description: "start a transaction history",
+        apply: v2_to_v3,
+    },
+];
+
+/// A migration step that was applied to a state document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStep {
+    /// Version before the step
+    pub from: u32,
+    /// Version after the step
+    pub to: u32,
+    /// What the step does
+    pub description: &'static str,
+    /// Each change the step made
+    pub changes: Vec<String>,
+}
+
+/// Version of a decrypted state document, 0 if it predates versioning
+pub fn state_version(state: &Value) -> Result<u32> {
+    match state.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| ClientError::CorruptState(format!("invalid state version {}", version))),
+    }
+}
+
+/// Upgrade a state document to [`STATE_VERSION`] in place
+///
+/// Returns the steps that were applied, which is empty if the state is current.
+pub fn migrate(state: &mut Value) -> Result<Vec<MigrationStep>> {
+    let mut version = state_version(state)?;
+    if version > STATE_VERSION {
+        return Err(ClientError::StateVersionTooNew {
+            found: version,
+            supported: STATE_VERSION,
+        });
+    }
+    let object = state
+        .as_object_mut()
+        .ok_or_else(|| ClientError::CorruptState("state is not a JSON object".to_string()))?;
+
+    let mut steps = Vec::new();
+    while version < STATE_VERSION {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == version)
+            .ok_or_else(|| {
+                ClientError::StateError(format!("No migration from state version {}", version))
+            })?;
+        let changes = (migration.apply)(object);
+        object.insert("version".to_string(), json!(version + 1));
+        steps.push(MigrationStep {
+            from: version,
+            to: version + 1,
+            description: migration.description,
+            changes,
+        });
+        version += 1;
+    }
+    Ok(steps)
+}
+
+/// Version 1 adds the `version` field itself and writes out every field that
+/// earlier clients filled in with serde defaults
+fn v0_to_v1(state: &mut Map<String, Value>) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    let wallet_defaults = [
+        ("view_only", json!({})),
+        ("mnemonic", Value::Null),
+        ("next_account_index", json!(0)),
+        // Wallets from before the hash version was recorded
+        ("hash_version", json!(1)),
+    ];
+    for (key, default) in wallet_defaults {
+        if !state.contains_key(key) {
+            changes.push(format!("add {} = {}", key, default));
+            state.insert(key.to_string(), default);
+        }
+    }
+
+    let note_defaults = [
+        ("blinding", json!(Field::ZERO)),
+        ("scheme", json!(HashScheme::Pedersen)),
+        ("secret", Value::Null),
+        ("block_number", Value::Null),
+    ];
+    let mut filled: BTreeMap<&str, (usize, &Value)> = BTreeMap::new();
+    let notes = state
+        .get_mut("commitments")
+        .and_then(Value::as_object_mut)
+        .into_iter()
+        .flat_map(|commitments| commitments.values_mut())
+        .filter_map(Value::as_object_mut);
+    for note in notes {
+        for (key, default) in &note_defaults {
+            if !note.contains_key(*key) {
+                note.insert(key.to_string(), default.clone());
+                filled.entry(key).or_insert((0, default)).0 += 1;
+            }
+        }
+    }
+    for (key, (count, default)) in filled {
+        changes.push(format!("add {} = {} to {} commitment(s)", key, default, count));
+    }
+
+    changes
+}
+
+/// Version 2 tracks pending transactions, so `spent: bool` becomes `status`
+fn v1_to_v2(state: &mut Map<String, Value>) -> Vec<String> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    let notes = state
+        .get_mut("commitments")
+        .and_then(Value::as_object_mut)
+        .into_iter()
+        .flat_map(|commitments| commitments.values_mut())
+        .filter_map(Value::as_object_mut);
+    for note in notes {
+        let spent = note.remove("spent").and_then(|spent| spent.as_bool()).unwrap_or(false);
+        let status = if spent {
+            NoteStatus::Spent
+        } else {
+            NoteStatus::Confirmed
+        };
+        note.insert("status".to_string(), json!(status));
+        *counts.entry(status.as_str()).or_default() += 1;
+    }
+
+    counts
+        .into_iter()
+        .map(|(status, count)| format!("set status = {} on {} commitment(s)", status, count))
+        .collect()
+}
+
+/// Version 3 keeps a history of operations, which starts out empty since older
+/// clients didn't record one
+fn v2_to_v3(state: &mut Map<String, Value>) -> Vec<String> {
+    if state.contains_key("history") {
+        return Vec::new();
+    }
+    state.insert("history".to_string(), json!([]));
+    vec!["add history = []".to_string()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrates_unversioned_state() {
+        let mut state = json!({
+            "commitments": {
+                "0x01": { "commitment": "0x01", "address": "0x02", "balance": 5, "nonce": 1,
+                          "spent": false, "blinding": "0x03" },
+                "0x04": { "commitment": "0x04", "address": "0x02", "balance": 7, "nonce": 2,
+                          "spent": true }
+            },
+            "accounts": {},
+            "hash_version": 2
+        });
+
+        let steps = migrate(&mut state).unwrap();
+        assert_eq!(steps.len(), 3);
+        assert_eq!((steps[0].from, steps[0].to), (0, 1));
+        assert_eq!((steps[1].from, steps[1].to), (1, 2));
+        assert_eq!(steps[2].changes, vec!["add history = []".to_string()]);
+        assert_eq!(
+            steps[1].changes,
+            vec![
+                "set status = confirmed on 1 commitment(s)".to_string(),
+                "set status = spent on 1 commitment(s)".to_string(),
+            ]
+        );
+        assert!(steps[0].changes.contains(&"add block_number = null to 2 commitment(s)".to_string()));
+        assert!(steps[0].changes.iter().any(|c| c.starts_with("add blinding") && c.contains(" 1 ")));
+        assert!(!steps[0].changes.iter().any(|c| c.starts_with("add hash_version")));
+
+        assert_eq!(state_version(&state).unwrap(), STATE_VERSION);
+        assert_eq!(state["commitments"]["0x01"]["blinding"], "0x03");
+        assert_eq!(state["commitments"]["0x04"]["scheme"], "pedersen");
+        assert_eq!(state["commitments"]["0x04"]["status"], "spent");
+        assert!(state["commitments"]["0x04"].get("spent").is_none());
+        assert_eq!(state["history"], json!([]));
+    }
+
+    #[test]
+    fn test_current_state_is_unchanged() {
+        let mut state = json!({ "version": STATE_VERSION, "commitments": {}, "accounts": {} });
+        let before = state.clone();
+        assert!(migrate(&mut state).unwrap().is_empty());
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn test_rejects_newer_state() {
+        let mut state = json!({ "version": STATE_VERSION + 1 });
+        assert!(matches!(
+            migrate(&mut state),
+            Err(ClientError::StateVersionTooNew { .. })
+        ));
+    }
+}
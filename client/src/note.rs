@@ -0,0 +1,307 @@
+//! Note encryption for delivering transfer outputs to recipients
+//!
+//! Notes are encrypted with ECDH over Grumpkin and ChaCha20-Poly1305. A recipient's
+//! encryption key is the x-coordinate of `ivk * G`, where `ivk` is the incoming
+//! viewing key. The sender picks an ephemeral scalar `e`, publishes `x(e * G)` and
+//! encrypts under a key derived from `x(e * P)`. Only x-coordinates are exchanged,
+//! which is sufficient because `x(e * P) == x(e * -P)`.
+//!
+//! A transfer publishes two notes: the recipient's, and the sender's change
+//! encrypted to the sender's own change key, `x(ovk * G)` for its outgoing viewing
+//! key `ovk`. Whoever holds the sender's viewing key can open the change, so a
+//! view-only wallet follows the account's balance through its spends.
+
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{BigInteger, PrimeField};
+use ark_grumpkin::{Affine, Fq, Fr};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::crypto;
+use crate::error::{ClientError, Result};
+use crate::field::Field;
+use crate::keys::ViewingKey;
+
+/// HKDF info string for note encryption keys
+const NOTE_KEY_INFO: &[u8] = b"private-token note encryption";
+
+/// Size of the serialized note plaintext
+const PLAINTEXT_LEN: usize = 16 + 8 + 32;
+
+/// Size of an encrypted note: ephemeral key, ciphertext and Poly1305 tag
+pub const ENCRYPTED_NOTE_LEN: usize = 32 + PLAINTEXT_LEN + 16;
+
+/// Concatenate the notes a transfer publishes: the recipient's, then the sender's
+/// change
+pub fn encode_transfer_notes(recipient: &[u8], change: &[u8]) -> Vec<u8> {
+    [recipient, change].concat()
+}
+
+/// Split the notes a transfer published into the recipient's and the change
+///
+/// Transfers that published only the recipient's note have no change.
+pub fn decode_transfer_notes(data: &[u8]) -> (&[u8], Option<&[u8]>) {
+    if data.len() == 2 * ENCRYPTED_NOTE_LEN {
+        let (recipient, change) = data.split_at(ENCRYPTED_NOTE_LEN);
+        (recipient, Some(change))
+    } else {
+        (data, None)
+    }
+}
+
+/// The private contents of a note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotePlaintext {
+    pub amount: u128,
+    pub nonce: u64,
+    pub blinding: Field,
+}
+
+impl NotePlaintext {
+    fn to_bytes(self) -> [u8; PLAINTEXT_LEN] {
+        let mut bytes = [0u8; PLAINTEXT_LEN];
+        bytes[..16].copy_from_slice(&self.amount.to_be_bytes());
+        bytes[16..24].copy_from_slice(&self.nonce.to_be_bytes());
+        bytes[24..].copy_from_slice(self.blinding.as_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != PLAINTEXT_LEN {
+            return None;
+        }
+        Some(Self {
+            amount: u128::from_be_bytes(bytes[..16].try_into().ok()?),
+            nonce: u64::from_be_bytes(bytes[16..24].try_into().ok()?),
+            blinding: Field::from_bytes(bytes[24..].try_into().ok()?).ok()?,
+        })
+    }
+}
+
+/// An encrypted note as emitted by the `PrivateTransfer` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedNote {
+    /// The output commitment the note opens
+    pub commitment: Field,
+    /// Ephemeral key followed by the AEAD ciphertext
+    pub ciphertext: Vec<u8>,
+    /// Block the note was emitted in, if known
+    pub block_number: Option<u64>,
+}
+
+/// Compute the public encryption key for a viewing key
+pub fn encryption_key(viewing_key: &ViewingKey) -> Field {
+    public_key(&viewing_key.incoming())
+}
+
+/// Compute the public key an account's change is encrypted to, from its outgoing
+/// viewing key
+pub fn change_key(viewing_key: &ViewingKey) -> Field {
+    public_key(&viewing_key.outgoing())
+}
+
+/// x-coordinate of `secret * G`
+fn public_key(secret: &Field) -> Field {
+    point_x(&(Affine::generator() * crypto::field_to_scalar(secret)).into_affine())
+}
+
+/// Encrypt a note to a recipient's encryption key, bound to its commitment
+pub fn encrypt_note(
+    recipient_key: &Field,
+    commitment: &Field,
+    note: &NotePlaintext,
+) -> Result<Vec<u8>> {
+    let recipient = point_from_x(recipient_key).ok_or_else(|| {
+        ClientError::InvalidInput(format!("Invalid recipient encryption key: {}", recipient_key))
+    })?;
+
+    let mut ephemeral_bytes = [0u8; 64];
+    rand::thread_rng().fill_bytes(&mut ephemeral_bytes);
+    let ephemeral = Fr::from_be_bytes_mod_order(&ephemeral_bytes);
+
+    let ephemeral_key = point_x(&(Affine::generator() * ephemeral).into_affine());
+    let shared = point_x(&(recipient * ephemeral).into_affine());
+
+    let cipher = note_cipher(&shared, &ephemeral_key);
+    let payload = Payload {
+        msg: &note.to_bytes(),
+        aad: commitment.as_bytes(),
+    };
+    let ciphertext = cipher
+        .encrypt(&Nonce::default(), payload)
+        .map_err(|_| ClientError::InvalidInput("Note encryption failed".to_string()))?;
+
+    let mut output = ephemeral_key.to_bytes().to_vec();
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+/// Attempt to decrypt a note with a viewing key
+///
+/// Returns `None` if the note was not encrypted to this key or was tampered with.
+pub fn try_decrypt_note(viewing_key: &ViewingKey, note: &EncryptedNote) -> Option<NotePlaintext> {
+    decrypt_with(&viewing_key.incoming(), note)
+}
+
+/// Attempt to decrypt the change of a transfer an account sent
+///
+/// Returns `None` if the note is not change encrypted to this key's
+/// [`change_key`], or was tampered with.
+pub fn try_decrypt_change(viewing_key: &ViewingKey, note: &EncryptedNote) -> Option<NotePlaintext> {
+    decrypt_with(&viewing_key.outgoing(), note)
+}
+
+/// Decrypt a note encrypted to `x(secret * G)`
+fn decrypt_with(secret: &Field, note: &EncryptedNote) -> Option<NotePlaintext> {
+    if note.ciphertext.len() != ENCRYPTED_NOTE_LEN {
+        return None;
+    }
+
+    let ephemeral_key = Field::from_bytes(note.ciphertext[..32].try_into().ok()?).ok()?;
+    let ephemeral = point_from_x(&ephemeral_key)?;
+    let shared = point_x(&(ephemeral * crypto::field_to_scalar(secret)).into_affine());
+
+    let cipher = note_cipher(&shared, &ephemeral_key);
+    let payload = Payload {
+        msg: &note.ciphertext[32..],
+        aad: note.commitment.as_bytes(),
+    };
+    let plaintext = cipher.decrypt(&Nonce::default(), payload).ok()?;
+    NotePlaintext::from_bytes(&plaintext)
+}
+
+/// Derive the symmetric cipher from the ECDH shared secret
+///
+/// Every note uses a fresh ephemeral key, so a fixed AEAD nonce is safe.
+fn note_cipher(shared: &Field, ephemeral_key: &Field) -> ChaCha20Poly1305 {
+    let hkdf = Hkdf::<Sha256>::new(Some(ephemeral_key.as_bytes()), shared.as_bytes());
+    let mut key = [0u8; 32];
+    hkdf.expand(NOTE_KEY_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    ChaCha20Poly1305::new(&Key::from(key))
+}
+
+/// x-coordinate of a Grumpkin point as a field element
+fn point_x(point: &Affine) -> Field {
+    Field::from_bytes_reduce(&point.x.into_bigint().to_bytes_be())
+}
+
+/// Recover a Grumpkin point from its x-coordinate
+fn point_from_x(x: &Field) -> Option<Affine> {
+    let x = Fq::from_be_bytes_mod_order(x.as_bytes());
+    Affine::get_point_from_x_unchecked(x, false).filter(|p| !p.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::SpendingKey;
+
+    fn random_viewing_key() -> ViewingKey {
+        SpendingKey::generate().viewing_key()
+    }
+
+    fn encrypt(viewing_key: &ViewingKey, commitment: Field, note: NotePlaintext) -> EncryptedNote {
+        let ciphertext = encrypt_note(&encryption_key(viewing_key), &commitment, &note).unwrap();
+        EncryptedNote {
+            commitment,
+            ciphertext,
+            block_number: None,
+        }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let viewing_key = random_viewing_key();
+        let note = NotePlaintext {
+            amount: 25,
+            nonce: 7,
+            blinding: crypto::generate_blinding(),
+        };
+        let encrypted = encrypt(&viewing_key, Field::from(1u64), note);
+
+        assert_eq!(encrypted.ciphertext.len(), ENCRYPTED_NOTE_LEN);
+        assert_eq!(try_decrypt_note(&viewing_key, &encrypted), Some(note));
+    }
+
+    #[test]
+    fn test_change_is_only_opened_with_the_outgoing_key() {
+        let viewing_key = random_viewing_key();
+        let note = NotePlaintext {
+            amount: 75,
+            nonce: 2,
+            blinding: crypto::generate_blinding(),
+        };
+        let commitment = Field::from(1u64);
+        let change = EncryptedNote {
+            commitment,
+            ciphertext: encrypt_note(&viewing_key.change_key(), &commitment, &note).unwrap(),
+            block_number: None,
+        };
+
+        assert_eq!(try_decrypt_change(&viewing_key, &change), Some(note));
+        assert_eq!(try_decrypt_note(&viewing_key, &change), None);
+        let received = encrypt(&viewing_key, commitment, note);
+        assert_eq!(try_decrypt_change(&viewing_key, &received), None);
+    }
+
+    #[test]
+    fn test_transfer_notes_roundtrip() {
+        let recipient = [1u8; ENCRYPTED_NOTE_LEN];
+        let change = [2u8; ENCRYPTED_NOTE_LEN];
+        let data = encode_transfer_notes(&recipient, &change);
+        assert_eq!(decode_transfer_notes(&data), (&recipient[..], Some(&change[..])));
+        assert_eq!(decode_transfer_notes(&recipient), (&recipient[..], None));
+    }
+
+    #[test]
+    fn test_wrong_key_cannot_decrypt() {
+        let note = NotePlaintext {
+            amount: 25,
+            nonce: 7,
+            blinding: crypto::generate_blinding(),
+        };
+        let encrypted = encrypt(&random_viewing_key(), Field::from(1u64), note);
+        assert_eq!(try_decrypt_note(&random_viewing_key(), &encrypted), None);
+    }
+
+    #[test]
+    fn test_note_is_bound_to_commitment() {
+        let viewing_key = random_viewing_key();
+        let note = NotePlaintext {
+            amount: 25,
+            nonce: 7,
+            blinding: crypto::generate_blinding(),
+        };
+        let mut encrypted = encrypt(&viewing_key, Field::from(1u64), note);
+        encrypted.commitment = Field::from(2u64);
+        assert_eq!(try_decrypt_note(&viewing_key, &encrypted), None);
+    }
+
+    #[test]
+    fn test_tampered_ciphertext_is_rejected() {
+        let viewing_key = random_viewing_key();
+        let note = NotePlaintext {
+            amount: 25,
+            nonce: 7,
+            blinding: crypto::generate_blinding(),
+        };
+        let mut encrypted = encrypt(&viewing_key, Field::from(1u64), note);
+        encrypted.ciphertext[40] ^= 1;
+        assert_eq!(try_decrypt_note(&viewing_key, &encrypted), None);
+    }
+
+    #[test]
+    fn test_invalid_recipient_key_is_rejected() {
+        // 0 is not the x-coordinate of a Grumpkin point (y^2 = -17 has no root)
+        let note = NotePlaintext {
+            amount: 1,
+            nonce: 0,
+            blinding: Field::ZERO,
+        };
+        assert!(encrypt_note(&Field::ZERO, &Field::from(1u64), &note).is_err());
+    }
+}
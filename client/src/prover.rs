@@ -1,154 +1,482 @@
 //! ZK Proof generation using Noir
+//!
+//! Circuits are the JSON artifacts `nargo compile` writes. Witnesses are solved in
+//! process: the inputs are ABI-encoded and the circuit's ACIR is executed by the ACVM.
+//! A [`ProverBackend`] then turns the witness into a proof.
+//!
+//! Verification keys are computed by the backend and cached next to the artifact,
+//! tagged with a hash of the circuit's bytecode and the backend's version, so
+//! recompiling a circuit or upgrading the backend invalidates its key.
 
+use std::fmt;
 use std::fs;
 use std::path::Path;
 use std::collections::BTreeMap;
 
+use acvm::acir::brillig::ForeignCallResult;
+use acvm::acir::circuit::Program;
+use acvm::acir::native_types::WitnessStack;
+use acvm::pwg::{ACVMStatus, ErrorLocation, OpcodeResolutionError, ResolvedAssertionPayload, ACVM};
+use acvm::FieldElement;
+use bn254_blackbox_solver::Bn254BlackBoxSolver;
+use noirc_abi::input_parser::InputValue;
+use noirc_abi::{display_abi_error, Abi, InputMap};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::bb::BbCli;
 use crate::error::{ClientError, Result};
+use crate::field::Field;
+use crate::keys::SpendingKey;
+use crate::store;
 
 /// Inputs for mint proof generation
 #[derive(Debug, Clone)]
 pub struct MintInputs {
-    pub recipient_secret: [u8; 32],
+    pub recipient_secret: SpendingKey,
     pub mint_amount: u128,
     pub nonce: u64,
-    pub output_commitment: [u8; 32],
+    pub blinding: Field,
+    pub output_commitment: Field,
     pub mint_request_id: u64,
 }
 
+impl MintInputs {
+    /// The mint circuit's arguments, by parameter name
+    pub fn input_map(&self) -> InputMap {
+        BTreeMap::from([
+            input("recipient_secret", *self.recipient_secret.expose_secret()),
+            input("mint_amount", self.mint_amount),
+            input("nonce", self.nonce),
+            input("blinding", self.blinding),
+            input("output_commitment", self.output_commitment),
+            input("mint_request_id", self.mint_request_id),
+        ])
+    }
+}
+
 /// Inputs for transfer proof generation
 #[derive(Debug, Clone)]
 pub struct TransferInputs {
-    pub sender_secret: [u8; 32],
+    pub sender_secret: SpendingKey,
     pub sender_balance: u128,
     pub transfer_amount: u128,
-    pub recipient_address: [u8; 32],
+    pub recipient_address: Field,
     pub sender_nonce: u64,
-    pub input_commitment: [u8; 32],
-    pub output_commitment_sender: [u8; 32],
-    pub output_commitment_recipient: [u8; 32],
-    pub nullifier: [u8; 32],
+    pub sender_blinding: Field,
+    pub new_blinding: Field,
+    pub recipient_blinding: Field,
+    pub input_commitment: Field,
+    pub output_commitment_sender: Field,
+    pub output_commitment_recipient: Field,
+    pub nullifier: Field,
     pub new_nonce: u64,
 }
 
+impl TransferInputs {
+    /// The transfer circuit's arguments, by parameter name
+    pub fn input_map(&self) -> InputMap {
+        BTreeMap::from([
+            input("sender_secret", *self.sender_secret.expose_secret()),
+            input("sender_balance", self.sender_balance),
+            input("transfer_amount", self.transfer_amount),
+            input("recipient_address", self.recipient_address),
+            input("sender_nonce", self.sender_nonce),
+            input("sender_blinding", self.sender_blinding),
+            input("new_blinding", self.new_blinding),
+            input("recipient_blinding", self.recipient_blinding),
+            input("input_commitment", self.input_commitment),
+            input("output_commitment_sender", self.output_commitment_sender),
+            input("output_commitment_recipient", self.output_commitment_recipient),
+            input("nullifier", self.nullifier),
+            input("new_nonce", self.new_nonce),
+        ])
+    }
+}
+
+/// A named `Field` argument of a circuit
+fn input(name: &str, value: impl Into<Field>) -> (String, InputValue) {
+    (name.to_string(), InputValue::Field(value.into().into()))
+}
+
 /// Generated proof data
 #[derive(Debug, Clone)]
 pub struct Proof {
     /// The proof bytes
     pub proof: Vec<u8>,
     /// Public inputs for verification
-    pub public_inputs: Vec<[u8; 32]>,
+    pub public_inputs: Vec<Field>,
 }
 
-/// Proof generator using Noir circuits
-pub struct ProofGenerator {
-    transfer_circuit_path: String,
-    mint_circuit_path: String,
+/// Which circuit a proof is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CircuitKind {
+    /// The mint circuit
+    Mint,
+    /// The private transfer circuit
+    Transfer,
 }
 
-impl ProofGenerator {
-    /// Create a new proof generator
-    pub fn new(transfer_circuit_path: &str, mint_circuit_path: &str) -> Result<Self> {
-        // Verify circuit files exist
-        if !Path::new(transfer_circuit_path).exists() {
-            return Err(ClientError::ProofError(format!(
-                "Transfer circuit not found: {}",
-                transfer_circuit_path
-            )));
+impl fmt::Display for CircuitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitKind::Mint => f.write_str("Mint"),
+            CircuitKind::Transfer => f.write_str("Transfer"),
         }
-        if !Path::new(mint_circuit_path).exists() {
+    }
+}
+
+/// A circuit's verification key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationKey {
+    /// The key, as the backend serializes it
+    #[serde(with = "hex")]
+    pub key: Vec<u8>,
+    /// Hash of the key, as `bb` computes it
+    #[serde(with = "hex")]
+    pub hash: [u8; 32],
+}
+
+impl VerificationKey {
+    /// The key hash as `0x`-prefixed hex
+    pub fn hash_hex(&self) -> String {
+        format!("0x{}", hex::encode(self.hash))
+    }
+}
+
+/// A cached verification key, and the bytecode and backend it was computed with
+#[derive(Serialize, Deserialize)]
+struct CachedKey {
+    #[serde(with = "hex")]
+    bytecode_hash: [u8; 32],
+    /// Empty in keys cached before the version was recorded
+    #[serde(default)]
+    backend_version: String,
+    #[serde(flatten)]
+    vk: VerificationKey,
+}
+
+/// The parts of a nargo artifact the client uses
+#[derive(Deserialize)]
+struct Artifact {
+    abi: Abi,
+    /// Base64 of the compressed ACIR program
+    #[serde(deserialize_with = "Program::deserialize_program_base64")]
+    bytecode: Program<FieldElement>,
+}
+
+/// A compiled circuit: its ABI and ACIR program, from a nargo artifact
+pub struct Circuit {
+    kind: CircuitKind,
+    /// Path of the artifact it was loaded from
+    path: String,
+    /// SHA-256 of the artifact's bytecode
+    bytecode_hash: [u8; 32],
+    abi: Abi,
+    program: Program<FieldElement>,
+}
+
+impl Circuit {
+    /// Load the artifact written by `nargo compile`
+    pub fn load(kind: CircuitKind, path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
             return Err(ClientError::ProofError(format!(
-                "Mint circuit not found: {}",
-                mint_circuit_path
+                "{} circuit not found: {}",
+                kind, path
             )));
         }
+        let json = load_circuit_json(path)?;
+        let bytecode_hash = Sha256::digest(json["bytecode"].as_str().unwrap_or_default()).into();
+        let artifact: Artifact = serde_json::from_value(json).map_err(|e| {
+            ClientError::ProofError(format!("{} is not a nargo artifact: {}", path, e))
+        })?;
+        if artifact.bytecode.functions.is_empty() {
+            return Err(ClientError::ProofError(format!("{} has no functions", path)));
+        }
+
+        Ok(Self {
+            kind,
+            path: path.to_string(),
+            bytecode_hash,
+            abi: artifact.abi,
+            program: artifact.bytecode,
+        })
+    }
+
+    /// Which circuit this is
+    pub fn kind(&self) -> CircuitKind {
+        self.kind
+    }
+
+    /// Path of the artifact the circuit was loaded from
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// SHA-256 of the circuit's bytecode, which changes when it is recompiled
+    pub fn bytecode_hash(&self) -> [u8; 32] {
+        self.bytecode_hash
+    }
+
+    /// Where the circuit's verification key is cached, e.g. `target/mint.vk.json`
+    pub fn vk_cache_path(&self) -> String {
+        Path::new(&self.path)
+            .with_extension("vk.json")
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// The cached verification key, unless the circuit has been recompiled since or
+    /// the key was computed by another version of the backend
+    pub fn cached_vk(&self, backend_version: &str) -> Option<VerificationKey> {
+        let data = fs::read(self.vk_cache_path()).ok()?;
+        match serde_json::from_slice::<CachedKey>(&data) {
+            Ok(cached) if cached.bytecode_hash != self.bytecode_hash => {
+                tracing::info!("{} circuit was recompiled since its key was cached", self.kind);
+                None
+            }
+            Ok(cached) if cached.backend_version != backend_version => {
+                tracing::info!(
+                    "{} circuit's key was cached by backend version {:?}, not {}",
+                    self.kind,
+                    cached.backend_version,
+                    backend_version
+                );
+                None
+            }
+            Ok(cached) => Some(cached.vk),
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable {}: {}", self.vk_cache_path(), e);
+                None
+            }
+        }
+    }
+
+    /// Cache a verification key computed for this build of the circuit
+    pub fn cache_vk(&self, backend_version: &str, vk: &VerificationKey) -> Result<()> {
+        let cached = CachedKey {
+            bytecode_hash: self.bytecode_hash,
+            backend_version: backend_version.to_string(),
+            vk: vk.clone(),
+        };
+        store::write_atomic(&self.vk_cache_path(), &serde_json::to_vec_pretty(&cached)?)
+    }
+
+    /// Solve the circuit's witness for `inputs`
+    ///
+    /// Fails with [`ClientError::ProofError`] if the inputs don't match the ABI, or
+    /// if they don't satisfy the circuit, naming the assertion that failed.
+    pub fn execute(&self, inputs: &InputMap) -> Result<WitnessStack<FieldElement>> {
+        let initial_witness = self.abi.encode(inputs, None).map_err(|e| {
+            ClientError::ProofError(format!("Invalid {} circuit inputs: {}", self.kind, e))
+        })?;
+
+        let main = &self.program.functions[0];
+        let solver = Bn254BlackBoxSolver(false);
+        let mut acvm = ACVM::new(
+            &solver,
+            &main.opcodes,
+            initial_witness,
+            &self.program.unconstrained_functions,
+            &main.assert_messages,
+        );
+        loop {
+            match acvm.solve() {
+                ACVMStatus::Solved => break,
+                ACVMStatus::InProgress => unreachable!("solve() runs until it stops"),
+                ACVMStatus::Failure(error) => return Err(self.failure(error)),
+                // Debug output from `println` in unconstrained code is dropped
+                ACVMStatus::RequiresForeignCall(call) if call.function == "print" => {
+                    acvm.resolve_pending_foreign_call(ForeignCallResult { values: Vec::new() });
+                }
+                ACVMStatus::RequiresForeignCall(call) => {
+                    return Err(ClientError::ProofError(format!(
+                        "{} circuit makes an unsupported oracle call: {}",
+                        self.kind, call.function
+                    )));
+                }
+                ACVMStatus::RequiresAcirCall(_) => {
+                    return Err(ClientError::ProofError(format!(
+                        "{} circuit calls other ACIR functions, which is not supported",
+                        self.kind
+                    )));
+                }
+            }
+        }
+
+        Ok(WitnessStack::from(acvm.finalize()))
+    }
+
+    /// Describe why solving failed, naming the failing assertion where possible
+    fn failure(&self, error: OpcodeResolutionError<FieldElement>) -> ClientError {
+        let message = match &error {
+            OpcodeResolutionError::UnsatisfiedConstrain {
+                payload: Some(payload),
+                ..
+            }
+            | OpcodeResolutionError::BrilligFunctionFailed {
+                payload: Some(payload),
+                ..
+            } => format!("assertion failed: {}", self.assertion_message(payload)),
+            OpcodeResolutionError::UnsatisfiedConstrain {
+                opcode_location: ErrorLocation::Resolved(location),
+                ..
+            } => format!("assertion failed at opcode {}", location),
+            _ => error.to_string(),
+        };
+        ClientError::ProofError(format!("{} circuit: {}", self.kind, message))
+    }
+
+    /// The message of a failed assertion, decoded through the ABI's error types
+    fn assertion_message(&self, payload: &ResolvedAssertionPayload<FieldElement>) -> String {
+        match payload {
+            ResolvedAssertionPayload::String(message) => message.clone(),
+            ResolvedAssertionPayload::Raw(raw) => match self.abi.error_types.get(&raw.selector) {
+                Some(error_type) => display_abi_error(&raw.data, error_type.clone()).to_string(),
+                None => format!("unknown error {:?}", raw.selector),
+            },
+        }
+    }
+}
+
+/// A proving system for UltraHonk proofs
+pub trait ProverBackend: fmt::Debug {
+    /// Prove a solved witness of `circuit`
+    fn prove(&self, circuit: &Circuit, witness: &WitnessStack<FieldElement>) -> Result<Proof>;
+
+    /// Check a proof against a verification key
+    ///
+    /// `Ok(false)` means the proof was checked and rejected; a key or proof that
+    /// couldn't be checked is an error.
+    fn verify(&self, vk: &[u8], proof: &Proof) -> Result<bool>;
+
+    /// Compute the verification key of `circuit`
+    fn write_vk(&self, circuit: &Circuit) -> Result<VerificationKey>;
+
+    /// Version of the proving system, which its verification keys depend on
+    fn version(&self) -> Result<String>;
+}
+
+/// Proof generator using Noir circuits
+pub struct ProofGenerator {
+    transfer_circuit: Circuit,
+    mint_circuit: Circuit,
+    backend: Box<dyn ProverBackend>,
+}
+
+impl ProofGenerator {
+    /// Create a new proof generator, loading both circuit artifacts and proving with `bb`
+    pub fn new(transfer_circuit_path: &str, mint_circuit_path: &str) -> Result<Self> {
+        Self::with_backend(
+            transfer_circuit_path,
+            mint_circuit_path,
+            Box::new(BbCli::from_env()),
+        )
+    }
 
+    /// Create a proof generator that proves with `backend`
+    pub fn with_backend(
+        transfer_circuit_path: &str,
+        mint_circuit_path: &str,
+        backend: Box<dyn ProverBackend>,
+    ) -> Result<Self> {
         Ok(Self {
-            transfer_circuit_path: transfer_circuit_path.to_string(),
-            mint_circuit_path: mint_circuit_path.to_string(),
+            transfer_circuit: Circuit::load(CircuitKind::Transfer, transfer_circuit_path)?,
+            mint_circuit: Circuit::load(CircuitKind::Mint, mint_circuit_path)?,
+            backend,
         })
     }
 
+    /// Solve the mint circuit's witness
+    pub fn mint_witness(&self, inputs: &MintInputs) -> Result<WitnessStack<FieldElement>> {
+        self.mint_circuit.execute(&inputs.input_map())
+    }
+
+    /// Solve the transfer circuit's witness
+    pub fn transfer_witness(&self, inputs: &TransferInputs) -> Result<WitnessStack<FieldElement>> {
+        self.transfer_circuit.execute(&inputs.input_map())
+    }
+
     /// Generate a mint proof
     /// 
-    /// This is a placeholder implementation. In production, you would:
-    /// 1. Load the compiled circuit from the JSON file
-    /// 2. Create the witness from the inputs
-    /// 3. Generate the proof using Barretenberg backend
+    /// The witness is solved in process, so inputs that don't satisfy the circuit
+    /// fail before the backend is run.
     pub fn generate_mint_proof(&self, inputs: MintInputs) -> Result<Proof> {
         tracing::info!("Generating mint proof...");
-        
-        // TODO: Implement actual proof generation using noir_rs or nargo
-        // For now, return a placeholder proof
-        
-        // In production, this would:
-        // 1. Load circuit: let circuit = load_circuit(&self.mint_circuit_path)?;
-        // 2. Create witness map from inputs
-        // 3. Execute circuit to generate witness
-        // 4. Generate proof using Barretenberg
-        
+
+        let witness = self.mint_witness(&inputs)?;
+        let proof = self.backend.prove(&self.mint_circuit, &witness)?;
+
         let public_inputs = vec![
             inputs.output_commitment,
-            crate::crypto::u64_to_bytes32(inputs.mint_request_id),
+            Field::from(inputs.mint_request_id),
         ];
+        check_public_inputs(&self.mint_circuit, &proof, &public_inputs)?;
 
-        // Placeholder proof - replace with actual proof generation
-        let proof = self.create_placeholder_proof(&public_inputs);
-
-        Ok(Proof {
-            proof,
-            public_inputs,
-        })
+        Ok(proof)
     }
 
     /// Generate a transfer proof
     pub fn generate_transfer_proof(&self, inputs: TransferInputs) -> Result<Proof> {
         tracing::info!("Generating transfer proof...");
-        
-        // TODO: Implement actual proof generation
-        
+
+        let witness = self.transfer_witness(&inputs)?;
+        let proof = self.backend.prove(&self.transfer_circuit, &witness)?;
+
         let public_inputs = vec![
             inputs.input_commitment,
             inputs.output_commitment_sender,
             inputs.output_commitment_recipient,
             inputs.nullifier,
-            crate::crypto::u64_to_bytes32(inputs.new_nonce),
+            Field::from(inputs.new_nonce),
         ];
+        check_public_inputs(&self.transfer_circuit, &proof, &public_inputs)?;
 
-        // Placeholder proof - replace with actual proof generation
-        let proof = self.create_placeholder_proof(&public_inputs);
+        Ok(proof)
+    }
 
-        Ok(Proof {
-            proof,
-            public_inputs,
-        })
+    /// The loaded circuit of a kind
+    pub fn circuit(&self, kind: CircuitKind) -> &Circuit {
+        match kind {
+            CircuitKind::Mint => &self.mint_circuit,
+            CircuitKind::Transfer => &self.transfer_circuit,
+        }
     }
 
-    /// Create a placeholder proof (for testing only)
-    fn create_placeholder_proof(&self, _public_inputs: &[[u8; 32]]) -> Vec<u8> {
-        // This is a placeholder - in production, use actual proof generation
-        // The proof would be generated by:
-        // 1. noir_rs crate for native Rust integration, or
-        // 2. Calling nargo prove via subprocess, or
-        // 3. Using the Barretenberg backend directly
-        
-        vec![0u8; 32] // Placeholder proof bytes
+    /// The verification key of a circuit, from the cache or computed and cached
+    pub fn verification_key(&self, kind: CircuitKind) -> Result<VerificationKey> {
+        let circuit = self.circuit(kind);
+        let version = self.backend.version()?;
+        if let Some(vk) = circuit.cached_vk(&version) {
+            return Ok(vk);
+        }
+
+        tracing::info!("Computing the {} circuit's verification key...", kind);
+        let vk = self.backend.write_vk(circuit)?;
+        circuit.cache_vk(&version, &vk)?;
+        Ok(vk)
     }
 
-    /// Verify a proof locally (optional, for debugging)
-    pub fn verify_proof(&self, proof: &Proof, is_transfer: bool) -> Result<bool> {
-        let circuit_path = if is_transfer {
-            &self.transfer_circuit_path
-        } else {
-            &self.mint_circuit_path
-        };
+    /// Verify a proof locally against the circuit's verification key
+    ///
+    /// Returns `Ok(false)` if the proof doesn't verify, and an error if it couldn't
+    /// be checked.
+    pub fn verify_proof(&self, proof: &Proof, kind: CircuitKind) -> Result<bool> {
+        tracing::info!("Verifying {} proof...", kind);
+        let vk = self.verification_key(kind)?;
+        self.backend.verify(&vk.key, proof)
+    }
+}
 
-        tracing::info!("Verifying proof using circuit: {}", circuit_path);
-        
-        // TODO: Implement actual verification
-        // For now, return true as placeholder
-        Ok(true)
+/// Check the backend proved the statement the contract will be shown
+fn check_public_inputs(circuit: &Circuit, proof: &Proof, expected: &[Field]) -> Result<()> {
+    if proof.public_inputs != expected {
+        return Err(ClientError::ProofError(format!(
+            "{} proof has public inputs {:?}, expected {:?}",
+            circuit.kind, proof.public_inputs, expected
+        )));
     }
+    Ok(())
 }
 
 /// Helper to load circuit JSON
@@ -161,47 +489,227 @@ fn load_circuit_json(path: &str) -> Result<serde_json::Value> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::{self, HashScheme};
+    use acvm::acir::circuit::{AssertionPayload, Circuit as AcirCircuit, Opcode, OpcodeLocation};
+    use acvm::acir::native_types::Expression;
+    use std::cell::Cell;
     use std::fs;
+    use std::rc::Rc;
     use tempfile::TempDir;
 
-    fn create_mock_circuit_files(dir: &TempDir) -> (String, String) {
-        let transfer_path = dir.path().join("transfer.json");
-        let mint_path = dir.path().join("mint.json");
-        
-        fs::write(&transfer_path, "{}").unwrap();
-        fs::write(&mint_path, "{}").unwrap();
-        
-        (
-            transfer_path.to_str().unwrap().to_string(),
-            mint_path.to_str().unwrap().to_string(),
-        )
+    /// Path of the mint circuit as `nargo compile` writes it
+    fn compiled_mint_path() -> String {
+        format!("{}/../circuits/mint/target/mint.json", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    /// A nargo artifact in `dir` holding one empty circuit
+    fn minimal_artifact(dir: &Path) -> String {
+        write_artifact(dir, Default::default(), serde_json::json!({}))
+    }
+
+    /// A nargo artifact in `dir` holding one circuit whose only constraint fails
+    /// with `message`, as `assert(false, message)` compiles
+    fn failing_artifact(dir: &Path, message: &str) -> String {
+        const SELECTOR: u64 = 0x5eed;
+        let circuit = AcirCircuit {
+            opcodes: vec![Opcode::AssertZero(Expression::one())],
+            assert_messages: vec![(
+                OpcodeLocation::Acir(0),
+                AssertionPayload {
+                    error_selector: SELECTOR,
+                    payload: Vec::new(),
+                },
+            )],
+            ..Default::default()
+        };
+        let error_types = serde_json::json!({
+            SELECTOR.to_string(): { "error_kind": "string", "string": message },
+        });
+        write_artifact(dir, circuit, error_types)
+    }
+
+    fn write_artifact(
+        dir: &Path,
+        circuit: AcirCircuit<FieldElement>,
+        error_types: serde_json::Value,
+    ) -> String {
+        let program = Program::<FieldElement> {
+            functions: vec![circuit],
+            unconstrained_functions: Vec::new(),
+        };
+        let bytecode =
+            Program::serialize_program_base64(&program, serde_json::value::Serializer).unwrap();
+        let artifact = serde_json::json!({
+            "abi": { "parameters": [], "return_type": null, "error_types": error_types },
+            "bytecode": bytecode,
+        });
+        let path = dir.join("mint.json");
+        fs::write(&path, artifact.to_string()).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// Accepts a proof whose bytes equal the verification key, and counts the keys
+    /// it computes
+    #[derive(Debug, Default)]
+    struct EchoBackend {
+        version: &'static str,
+        keys_computed: Rc<Cell<usize>>,
+    }
+
+    impl ProverBackend for EchoBackend {
+        fn prove(
+            &self,
+            _circuit: &Circuit,
+            _witness: &WitnessStack<FieldElement>,
+        ) -> Result<Proof> {
+            Err(ClientError::ProofError("EchoBackend only verifies".to_string()))
+        }
+
+        fn verify(&self, vk: &[u8], proof: &Proof) -> Result<bool> {
+            Ok(proof.proof == vk)
+        }
+
+        fn write_vk(&self, _circuit: &Circuit) -> Result<VerificationKey> {
+            self.keys_computed.set(self.keys_computed.get() + 1);
+            Ok(VerificationKey {
+                key: b"key".to_vec(),
+                hash: [7; 32],
+            })
+        }
+
+        fn version(&self) -> Result<String> {
+            Ok(self.version.to_string())
+        }
+    }
+
+    fn mint_inputs() -> MintInputs {
+        let recipient_secret = SpendingKey::from_field(Field::from(1u64));
+        let blinding = Field::from(3u64);
+        MintInputs {
+            output_commitment: crypto::compute_commitment(
+                HashScheme::Pedersen,
+                &recipient_secret.address(),
+                100,
+                1,
+                &blinding,
+            ),
+            recipient_secret,
+            mint_amount: 100,
+            nonce: 1,
+            blinding,
+            mint_request_id: 1,
+        }
     }
 
     #[test]
-    fn test_proof_generator_creation() {
+    fn test_proof_generator_rejects_missing_or_invalid_artifacts() {
         let dir = TempDir::new().unwrap();
-        let (transfer_path, mint_path) = create_mock_circuit_files(&dir);
-        
-        let generator = ProofGenerator::new(&transfer_path, &mint_path);
-        assert!(generator.is_ok());
+        let path = dir.path().join("mint.json");
+        let path = path.to_str().unwrap();
+
+        assert!(matches!(
+            Circuit::load(CircuitKind::Mint, path),
+            Err(ClientError::ProofError(_))
+        ));
+        fs::write(path, "{}").unwrap();
+        assert!(matches!(
+            ProofGenerator::new(path, path),
+            Err(ClientError::ProofError(_))
+        ));
     }
 
     #[test]
-    fn test_generate_mint_proof() {
+    fn test_input_map() {
+        let inputs = mint_inputs();
+        let map = inputs.input_map();
+
+        let names: Vec<&str> = map.keys().map(String::as_str).collect();
+        assert_eq!(
+            names,
+            [
+                "blinding",
+                "mint_amount",
+                "mint_request_id",
+                "nonce",
+                "output_commitment",
+                "recipient_secret",
+            ]
+        );
+        assert_eq!(map["mint_amount"], InputValue::Field(FieldElement::from(100u128)));
+        assert_eq!(
+            map["recipient_secret"],
+            InputValue::Field((*inputs.recipient_secret.expose_secret()).into())
+        );
+    }
+
+    #[test]
+    fn test_execute_reports_the_failed_assertion() {
         let dir = TempDir::new().unwrap();
-        let (transfer_path, mint_path) = create_mock_circuit_files(&dir);
-        
-        let generator = ProofGenerator::new(&transfer_path, &mint_path).unwrap();
-        
-        let inputs = MintInputs {
-            recipient_secret: [1u8; 32],
-            mint_amount: 100,
-            nonce: 1,
-            output_commitment: [2u8; 32],
-            mint_request_id: 1,
+        let circuit = Circuit::load(CircuitKind::Mint, &minimal_artifact(dir.path())).unwrap();
+        assert!(circuit.execute(&InputMap::new()).is_ok());
+
+        let path = failing_artifact(dir.path(), "output commitment does not match the note");
+        let circuit = Circuit::load(CircuitKind::Mint, &path).unwrap();
+        match circuit.execute(&InputMap::new()) {
+            Err(ClientError::ProofError(message)) => assert_eq!(
+                message,
+                "Mint circuit: assertion failed: output commitment does not match the note"
+            ),
+            result => panic!("expected a failed assertion, got {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    #[ignore = "needs circuits/mint compiled with `nargo compile`"]
+    fn test_execute_mint_circuit() {
+        let circuit = Circuit::load(CircuitKind::Mint, &compiled_mint_path()).unwrap();
+
+        assert!(circuit.execute(&mint_inputs().input_map()).is_ok());
+
+        let mut inputs = mint_inputs();
+        inputs.mint_amount = 99;
+        let err = circuit.execute(&inputs.input_map()).unwrap_err();
+        assert!(err.to_string().contains("output commitment does not match the note"));
+    }
+
+    #[test]
+    fn test_verification_key_is_cached_per_build() {
+        let dir = TempDir::new().unwrap();
+        let path = minimal_artifact(dir.path());
+        let path = path.as_str();
+        let cache_path = dir.path().join("mint.vk.json");
+
+        let keys_computed = Rc::new(Cell::new(0));
+        let generator_for = |version| {
+            let backend = EchoBackend {
+                version,
+                keys_computed: keys_computed.clone(),
+            };
+            ProofGenerator::with_backend(path, path, Box::new(backend)).unwrap()
         };
-        
-        let proof = generator.generate_mint_proof(inputs);
-        assert!(proof.is_ok());
+        let generator = || generator_for("3.0.0");
+        let proof = |bytes: &[u8]| Proof {
+            proof: bytes.to_vec(),
+            public_inputs: Vec::new(),
+        };
+
+        assert!(generator().verify_proof(&proof(b"key"), CircuitKind::Mint).unwrap());
+        assert!(!generator().verify_proof(&proof(b"forged"), CircuitKind::Mint).unwrap());
+        assert_eq!(keys_computed.get(), 1);
+        assert_eq!(generator().verification_key(CircuitKind::Mint).unwrap().hash, [7; 32]);
+
+        // A key cached for other bytecode is computed again
+        let mut cache: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&cache_path).unwrap()).unwrap();
+        cache["bytecode_hash"] = hex::encode([0u8; 32]).into();
+        fs::write(&cache_path, cache.to_string()).unwrap();
+        assert!(generator().verify_proof(&proof(b"key"), CircuitKind::Mint).unwrap());
+        assert_eq!(keys_computed.get(), 2);
+
+        // So is a key cached by another version of the backend
+        assert!(generator_for("3.1.0").verify_proof(&proof(b"key"), CircuitKind::Mint).unwrap());
+        assert_eq!(keys_computed.get(), 3);
+        generator_for("3.1.0").verification_key(CircuitKind::Mint).unwrap();
+        assert_eq!(keys_computed.get(), 3);
     }
 }
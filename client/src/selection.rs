@@ -0,0 +1,196 @@
+//! Coin selection: which note a transfer spends
+//!
+//! The transfer circuit spends a single note, so a strategy picks one note out of
+//! those that cover the amount. The candidates are sorted by balance and then by
+//! commitment before a strategy sees them, so the choice doesn't depend on the
+//! order the store returned them in.
+
+use rand::seq::SliceRandom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{ClientError, Result};
+use crate::state::Commitment;
+
+/// A strategy for choosing the note to spend
+pub trait CoinSelector: fmt::Debug {
+    /// Choose one of `candidates`, each of which covers the amount
+    ///
+    /// `candidates` is never empty and is sorted by balance, smallest first.
+    fn choose<'a>(&self, candidates: &[&'a Commitment], amount: u128) -> &'a Commitment;
+}
+
+/// Spend the largest note, which keeps the number of notes down
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestFirst;
+
+impl CoinSelector for LargestFirst {
+    fn choose<'a>(&self, candidates: &[&'a Commitment], _amount: u128) -> &'a Commitment {
+        candidates[candidates.len() - 1]
+    }
+}
+
+/// Spend the smallest note that covers the amount, which keeps large notes intact
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmallestSufficient;
+
+impl CoinSelector for SmallestSufficient {
+    fn choose<'a>(&self, candidates: &[&'a Commitment], _amount: u128) -> &'a Commitment {
+        candidates[0]
+    }
+}
+
+/// Spend a random note that covers the amount
+///
+/// A deterministic strategy lets an observer who knows some of the wallet's notes
+/// predict which one a transfer spends. Choosing at random avoids that.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrivacyPreserving;
+
+impl CoinSelector for PrivacyPreserving {
+    fn choose<'a>(&self, candidates: &[&'a Commitment], _amount: u128) -> &'a Commitment {
+        candidates
+            .choose(&mut rand::thread_rng())
+            .expect("candidates is never empty")
+    }
+}
+
+/// The built-in strategies, as chosen on the command line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CoinSelection {
+    /// See [`LargestFirst`]
+    LargestFirst,
+    /// See [`SmallestSufficient`]
+    #[default]
+    SmallestSufficient,
+    /// See [`PrivacyPreserving`]
+    Privacy,
+}
+
+impl CoinSelection {
+    /// The selector implementing this strategy
+    pub fn selector(self) -> Box<dyn CoinSelector> {
+        match self {
+            CoinSelection::LargestFirst => Box::new(LargestFirst),
+            CoinSelection::SmallestSufficient => Box::new(SmallestSufficient),
+            CoinSelection::Privacy => Box::new(PrivacyPreserving),
+        }
+    }
+}
+
+impl fmt::Display for CoinSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoinSelection::LargestFirst => f.write_str("largest-first"),
+            CoinSelection::SmallestSufficient => f.write_str("smallest-sufficient"),
+            CoinSelection::Privacy => f.write_str("privacy"),
+        }
+    }
+}
+
+impl FromStr for CoinSelection {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "largest-first" => Ok(CoinSelection::LargestFirst),
+            "smallest-sufficient" => Ok(CoinSelection::SmallestSufficient),
+            "privacy" => Ok(CoinSelection::Privacy),
+            _ => Err(ClientError::InvalidInput(format!(
+                "Unknown coin selection strategy: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Pick the note to spend for `amount` out of an address's spendable `notes`
+///
+/// Fails with [`ClientError::InsufficientBalance`] if the notes don't add up to the
+/// amount, and with [`ClientError::NoSufficientNote`] if they do but no single
+/// note covers it.
+pub fn select_note(
+    selector: &dyn CoinSelector,
+    notes: &[Commitment],
+    amount: u128,
+) -> Result<Commitment> {
+    let total = notes.iter().map(|c| c.balance).sum::<u128>();
+    if total < amount {
+        return Err(ClientError::InsufficientBalance {
+            have: total,
+            need: amount,
+        });
+    }
+
+    let mut candidates: Vec<&Commitment> = notes.iter().filter(|c| c.balance >= amount).collect();
+    if candidates.is_empty() {
+        let largest = notes.iter().map(|c| c.balance).max().unwrap_or(0);
+        return Err(ClientError::NoSufficientNote {
+            largest,
+            need: amount,
+            total,
+        });
+    }
+    candidates.sort_by_key(|c| (c.balance, c.commitment));
+    Ok(selector.choose(&candidates, amount).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::Field;
+
+    fn notes(balances: &[u128]) -> Vec<Commitment> {
+        balances
+            .iter()
+            .enumerate()
+            .map(|(i, &balance)| Commitment::test_note(i as u64 + 1, Field::from(0xabcdu64), balance))
+            .collect()
+    }
+
+    #[test]
+    fn test_strategies() {
+        let notes = notes(&[50, 10, 200, 30]);
+        let pick = |selection: CoinSelection, amount| {
+            select_note(selection.selector().as_ref(), &notes, amount).unwrap().balance
+        };
+
+        assert_eq!(pick(CoinSelection::LargestFirst, 20), 200);
+        assert_eq!(pick(CoinSelection::SmallestSufficient, 20), 30);
+        assert_eq!(pick(CoinSelection::SmallestSufficient, 30), 30);
+        for _ in 0..20 {
+            assert!([30, 50, 200].contains(&pick(CoinSelection::Privacy, 20)));
+        }
+    }
+
+    #[test]
+    fn test_errors_distinguish_total_from_single_note() {
+        let notes = notes(&[50, 10, 30]);
+        let selector = CoinSelection::default().selector();
+
+        assert!(matches!(
+            select_note(selector.as_ref(), &notes, 100),
+            Err(ClientError::InsufficientBalance { have: 90, need: 100 })
+        ));
+        assert!(matches!(
+            select_note(selector.as_ref(), &notes, 60),
+            Err(ClientError::NoSufficientNote { largest: 50, need: 60, total: 90 })
+        ));
+        assert!(matches!(
+            select_note(selector.as_ref(), &[], 1),
+            Err(ClientError::InsufficientBalance { have: 0, need: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        for selection in [
+            CoinSelection::LargestFirst,
+            CoinSelection::SmallestSufficient,
+            CoinSelection::Privacy,
+        ] {
+            assert_eq!(selection.to_string().parse::<CoinSelection>().unwrap(), selection);
+        }
+        assert!("random".parse::<CoinSelection>().is_err());
+    }
+}
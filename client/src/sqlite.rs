@@ -0,0 +1,712 @@
+//! SQLite wallet store
+//!
+//! Each note is one row, encrypted with the wallet key. Rows are looked up by
+//! keyed hashes of their commitment and address, so the database can be indexed
+//! without revealing which addresses or on-chain notes belong to the wallet. The
+//! note status and block number are stored in the clear so they can be indexed too.
+//! History entries are stored the same way, with their status and timestamp in
+//! the clear.
+
+use rusqlite::{params, Connection, OptionalExtension, Params};
+use std::fs::File;
+
+use crate::error::{ClientError, Result};
+use crate::field::Field;
+use crate::history::{HistoryEntry, OperationStatus};
+use crate::migrate::MigrationStep;
+use crate::state::{Commitment, Keyring, NoteStatus};
+use crate::store::{self, NoteBatch, WalletStore};
+use crate::vault::{KdfParams, WalletCipher};
+
+/// Schema version, stored as `PRAGMA user_version`
+const SCHEMA_VERSION: u32 = 3;
+
+/// Tables and indexes of a new database
+const SCHEMA: &str = "
+    CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value BLOB NOT NULL
+    );
+    CREATE TABLE notes (
+        tag BLOB PRIMARY KEY,
+        address_tag BLOB NOT NULL,
+        status TEXT NOT NULL DEFAULT 'confirmed',
+        block_number INTEGER,
+        data BLOB NOT NULL
+    );
+    CREATE INDEX notes_by_address ON notes (address_tag, status);
+    CREATE INDEX notes_by_status ON notes (status);
+    CREATE INDEX notes_by_block ON notes (block_number);
+    CREATE TABLE history (
+        tag BLOB PRIMARY KEY,
+        address_tag BLOB NOT NULL,
+        status TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE INDEX history_by_address ON history (address_tag, timestamp);
+    CREATE INDEX history_by_status ON history (status);
+";
+
+/// A schema upgrade from `from` to `from + 1`
+struct SchemaMigration {
+    from: u32,
+    description: &'static str,
+    sql: &'static str,
+    /// Describe what `sql` would change in a database at version `from`
+    changes: fn(&Connection) -> Result<Vec<String>>,
+}
+
+/// Schema upgrades, in order
+const SCHEMA_MIGRATIONS: &[SchemaMigration] = &[
+    SchemaMigration {
+        from: 1,
+        description: "replace the spent flag with a note status",
+        sql: "
+            ALTER TABLE notes ADD COLUMN status TEXT NOT NULL DEFAULT 'confirmed';
+            UPDATE notes SET status = 'spent' WHERE spent = 1;
+            DROP INDEX notes_by_address;
+            DROP INDEX notes_by_spent;
+            ALTER TABLE notes DROP COLUMN spent;
+            CREATE INDEX notes_by_address ON notes (address_tag, status);
+            CREATE INDEX notes_by_status ON notes (status);
+        ",
+        changes: spent_flag_changes,
+    },
+    SchemaMigration {
+        from: 2,
+        description: "start a transaction history",
+        sql: "
+            CREATE TABLE history (
+                tag BLOB PRIMARY KEY,
+                address_tag BLOB NOT NULL,
+                status TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                data BLOB NOT NULL
+            );
+            CREATE INDEX history_by_address ON history (address_tag, timestamp);
+            CREATE INDEX history_by_status ON history (status);
+        ",
+        changes: |_| Ok(vec!["add an empty history table".to_string()]),
+    },
+];
+
+/// Columns every note query selects, in the order `read_rows` expects
+const NOTE_COLUMNS: &str = "tag, status, block_number, data";
+
+/// Columns every history query selects, in the order `query_history` expects
+const HISTORY_COLUMNS: &str = "tag, status, data";
+
+/// Prefix that separates history tags from note tags
+const HISTORY_TAG_PREFIX: &[u8] = b"history";
+
+/// `meta` key of the password KDF parameters
+const META_KDF: &str = "kdf";
+
+/// `meta` key of the encrypted keyring
+const META_KEYRING: &str = "keyring";
+
+/// Wallet kept in an SQLite database
+#[derive(Debug)]
+pub struct SqliteStore {
+    conn: Connection,
+    cipher: WalletCipher,
+    /// Advisory lock on the wallet, released when the store is dropped
+    _lock: File,
+}
+
+impl SqliteStore {
+    /// Unlock the database at `path`, or create one encrypted under `password`
+    ///
+    /// An older schema is upgraded after copying the database aside.
+    pub fn open(path: &str, password: &str) -> Result<Self> {
+        let lock = store::lock_state_file(path)?;
+        let mut conn = Connection::open(path)?;
+
+        let version = schema_version(&conn)?;
+        if version == 0 {
+            let cipher = WalletCipher::new(password)?;
+            let tx = conn.transaction()?;
+            tx.execute_batch(SCHEMA)?;
+            tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+            write_meta(&tx, &cipher, &Keyring::new())?;
+            tx.commit()?;
+            return Ok(Self {
+                conn,
+                cipher,
+                _lock: lock,
+            });
+        }
+
+        let mut store = Self {
+            cipher: unlock(&conn, password)?,
+            conn,
+            _lock: lock,
+        };
+        if version < SCHEMA_VERSION {
+            // `VACUUM INTO` refuses to overwrite, so a backup left by an earlier
+            // attempt is replaced by renaming a fresh copy over it
+            let backup = store::migration_backup_path(path, version);
+            let temp = store::sibling_path(&backup, store::TEMP_SUFFIX);
+            remove_if_exists(&temp)?;
+            store.conn.execute("VACUUM INTO ?1", [&temp])?;
+            std::fs::rename(&temp, &backup)?;
+            let tx = store.conn.transaction()?;
+            for migration in pending_migrations(version)? {
+                tx.execute_batch(migration.sql)?;
+                tracing::info!(
+                    "Migrated database from version {} to {}",
+                    migration.from,
+                    migration.from + 1
+                );
+            }
+            tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+            tx.commit()?;
+        }
+        Ok(store)
+    }
+
+    /// Report the schema upgrades opening the database would apply
+    pub fn plan_migration(path: &str, password: &str) -> Result<Vec<MigrationStep>> {
+        let _lock = store::lock_state_file(path)?;
+        let conn = Connection::open(path)?;
+        let version = schema_version(&conn)?;
+        if version == 0 {
+            return Ok(Vec::new());
+        }
+        unlock(&conn, password)?;
+
+        pending_migrations(version)?
+            .iter()
+            .map(|migration| {
+                Ok(MigrationStep {
+                    from: migration.from,
+                    to: migration.from + 1,
+                    description: migration.description,
+                    changes: (migration.changes)(&conn)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Run a note query selecting [`NOTE_COLUMNS`] and decrypt every row it returns
+    fn query_notes(&self, sql: &str, params: impl Params) -> Result<Vec<Commitment>> {
+        let mut statement = self.conn.prepare(sql)?;
+        let rows = statement
+            .query_map(params, |row| {
+                let row: (Vec<u8>, String, Option<u64>, Vec<u8>) =
+                    (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+                Ok(row)
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter()
+            .map(|(tag, status, block_number, data)| {
+                decrypt_note(&self.cipher, &tag, status.parse()?, block_number, &data)
+            })
+            .collect()
+    }
+
+    /// Run a history query selecting [`HISTORY_COLUMNS`] and decrypt every row it returns
+    fn query_history(&self, sql: &str, params: impl Params) -> Result<Vec<HistoryEntry>> {
+        let mut statement = self.conn.prepare(sql)?;
+        let rows = statement
+            .query_map(params, |row| {
+                let row: (Vec<u8>, String, Vec<u8>) = (row.get(0)?, row.get(1)?, row.get(2)?);
+                Ok(row)
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter()
+            .map(|(tag, status, data)| {
+                let plaintext = self.cipher.decrypt(&tag, &data)?;
+                let mut entry: HistoryEntry = serde_json::from_slice(&plaintext)?;
+                entry.status = status.parse()?;
+                Ok(entry)
+            })
+            .collect()
+    }
+}
+
+impl WalletStore for SqliteStore {
+    fn load_keyring(&self) -> Result<Keyring> {
+        read_keyring(&self.conn, &self.cipher)
+    }
+
+    fn save_keyring(&mut self, keyring: &Keyring) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        write_meta(&tx, &self.cipher, keyring)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_commitment(&self, commitment: &Field) -> Result<Option<Commitment>> {
+        let tag = self.cipher.index_tag(commitment.as_bytes());
+        let notes = self.query_notes(
+            &format!("SELECT {} FROM notes WHERE tag = ?1", NOTE_COLUMNS),
+            [tag.as_slice()],
+        )?;
+        Ok(notes.into_iter().next())
+    }
+
+    fn commitments(&self, address: &Field, status: NoteStatus) -> Result<Vec<Commitment>> {
+        let address_tag = self.cipher.index_tag(address.as_bytes());
+        self.query_notes(
+            &format!(
+                "SELECT {} FROM notes WHERE address_tag = ?1 AND status = ?2",
+                NOTE_COLUMNS
+            ),
+            params![address_tag.as_slice(), status.as_str()],
+        )
+    }
+
+    fn pending_commitments(&self) -> Result<Vec<Commitment>> {
+        self.query_notes(
+            &format!("SELECT {} FROM notes WHERE status IN (?1, ?2)", NOTE_COLUMNS),
+            [NoteStatus::PendingOutput.as_str(), NoteStatus::PendingSpend.as_str()],
+        )
+    }
+
+    fn commitments_since_block(&self, block_number: u64) -> Result<Vec<Commitment>> {
+        self.query_notes(
+            &format!("SELECT {} FROM notes WHERE block_number >= ?1", NOTE_COLUMNS),
+            [block_number],
+        )
+    }
+
+    fn all_commitments(&self) -> Result<Vec<Commitment>> {
+        self.query_notes(&format!("SELECT {} FROM notes", NOTE_COLUMNS), [])
+    }
+
+    fn history(&self, address: Option<&Field>) -> Result<Vec<HistoryEntry>> {
+        match address {
+            Some(address) => {
+                let address_tag = self.cipher.index_tag(address.as_bytes());
+                self.query_history(
+                    &format!(
+                        "SELECT {} FROM history WHERE address_tag = ?1 ORDER BY timestamp, rowid",
+                        HISTORY_COLUMNS
+                    ),
+                    [address_tag.as_slice()],
+                )
+            }
+            None => self.query_history(
+                &format!("SELECT {} FROM history ORDER BY timestamp, rowid", HISTORY_COLUMNS),
+                [],
+            ),
+        }
+    }
+
+    fn pending_history(&self) -> Result<Vec<HistoryEntry>> {
+        self.query_history(
+            &format!("SELECT {} FROM history WHERE status = ?1", HISTORY_COLUMNS),
+            [OperationStatus::Pending.as_str()],
+        )
+    }
+
+    fn apply(&mut self, batch: NoteBatch) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        if let Some(keyring) = &batch.keyring {
+            write_meta(&tx, &self.cipher, keyring)?;
+        }
+        for commitment in &batch.insert {
+            insert_note(&tx, &self.cipher, commitment)?;
+        }
+        for hash in &batch.remove {
+            let tag = self.cipher.index_tag(hash.as_bytes());
+            tx.execute("DELETE FROM notes WHERE tag = ?1", [tag.as_slice()])?;
+        }
+        for entry in &batch.history {
+            insert_history(&tx, &self.cipher, entry)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn change_password(&mut self, password: &str) -> Result<()> {
+        let keyring = self.load_keyring()?;
+        let notes = self.all_commitments()?;
+        let history = self.history(None)?;
+        let cipher = WalletCipher::new(password)?;
+
+        // Index tags are keyed, so every row is rewritten under the new key
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM notes", [])?;
+        for commitment in &notes {
+            insert_note(&tx, &cipher, commitment)?;
+        }
+        tx.execute("DELETE FROM history", [])?;
+        for entry in &history {
+            insert_history(&tx, &cipher, entry)?;
+        }
+        write_meta(&tx, &cipher, &keyring)?;
+        tx.commit()?;
+
+        self.cipher = cipher;
+        Ok(())
+    }
+}
+
+/// Schema version of a database, 0 if it is new
+fn schema_version(conn: &Connection) -> Result<u32> {
+    let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(ClientError::StateVersionTooNew {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    Ok(version)
+}
+
+/// Schema upgrades that bring `version` up to date
+fn pending_migrations(version: u32) -> Result<Vec<&'static SchemaMigration>> {
+    (version..SCHEMA_VERSION)
+        .map(|from| {
+            SCHEMA_MIGRATIONS
+                .iter()
+                .find(|migration| migration.from == from)
+                .ok_or_else(|| {
+                    ClientError::StateError(format!("No migration from schema version {}", from))
+                })
+        })
+        .collect()
+}
+
+/// Count the notes each status is taken from the version 1 `spent` flag
+fn spent_flag_changes(conn: &Connection) -> Result<Vec<String>> {
+    let mut statement =
+        conn.prepare("SELECT spent, COUNT(*) FROM notes GROUP BY spent ORDER BY spent")?;
+    let counts = statement
+        .query_map([], |row| Ok((row.get::<_, bool>(0)?, row.get::<_, u64>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut changes: Vec<String> = counts
+        .into_iter()
+        .map(|(spent, count)| {
+            let status = if spent { NoteStatus::Spent } else { NoteStatus::Confirmed };
+            format!("set status = {} on {} note(s)", status.as_str(), count)
+        })
+        .collect();
+    changes.push("drop spent".to_string());
+    Ok(changes)
+}
+
+/// Remove a file, if there is one
+fn remove_if_exists(path: &str) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// Derive the cipher for an existing database and check the password against it
+fn unlock(conn: &Connection, password: &str) -> Result<WalletCipher> {
+    let kdf: Vec<u8> = conn
+        .query_row("SELECT value FROM meta WHERE key = ?1", [META_KDF], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| ClientError::CorruptState("database has no KDF parameters".to_string()))?;
+    let kdf: KdfParams = serde_json::from_slice(&kdf)?;
+    let cipher = WalletCipher::derive(kdf, password)?;
+
+    // Decrypting the keyring checks the password
+    read_keyring(conn, &cipher)?.check_hash_version()?;
+    Ok(cipher)
+}
+
+/// Read and decrypt the keyring
+fn read_keyring(conn: &Connection, cipher: &WalletCipher) -> Result<Keyring> {
+    let data: Vec<u8> = conn.query_row(
+        "SELECT value FROM meta WHERE key = ?1",
+        [META_KEYRING],
+        |row| row.get(0),
+    )?;
+    let plaintext = cipher.decrypt(META_KEYRING.as_bytes(), &data)?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Write the KDF parameters and the encrypted keyring
+fn write_meta(conn: &Connection, cipher: &WalletCipher, keyring: &Keyring) -> Result<()> {
+    let plaintext = zeroize::Zeroizing::new(serde_json::to_vec(keyring)?);
+    let keyring = cipher.encrypt(META_KEYRING.as_bytes(), &plaintext)?;
+    let kdf = serde_json::to_vec(cipher.kdf())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2), (?3, ?4)",
+        params![META_KDF, kdf, META_KEYRING, keyring],
+    )?;
+    Ok(())
+}
+
+/// Encrypt and insert a note, replacing any row for the same commitment
+fn insert_note(conn: &Connection, cipher: &WalletCipher, commitment: &Commitment) -> Result<()> {
+    let tag = cipher.index_tag(commitment.commitment.as_bytes());
+    let address_tag = cipher.index_tag(commitment.address.as_bytes());
+    let plaintext = zeroize::Zeroizing::new(serde_json::to_vec(commitment)?);
+    let data = cipher.encrypt(&tag, &plaintext)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO notes (tag, address_tag, status, block_number, data)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            tag.as_slice(),
+            address_tag.as_slice(),
+            commitment.status.as_str(),
+            commitment.block_number,
+            data
+        ],
+    )?;
+    Ok(())
+}
+
+/// Encrypt and store a history entry, replacing any entry with the same kind and id
+///
+/// A replaced entry keeps its row, so entries recorded in the same second stay in order.
+fn insert_history(conn: &Connection, cipher: &WalletCipher, entry: &HistoryEntry) -> Result<()> {
+    let key = [
+        HISTORY_TAG_PREFIX,
+        entry.kind.as_str().as_bytes(),
+        entry.id.as_bytes().as_slice(),
+    ]
+    .concat();
+    let tag = cipher.index_tag(&key);
+    let address_tag = cipher.index_tag(entry.address.as_bytes());
+    let plaintext = zeroize::Zeroizing::new(serde_json::to_vec(entry)?);
+    let data = cipher.encrypt(&tag, &plaintext)?;
+    conn.execute(
+        "INSERT INTO history (tag, address_tag, status, timestamp, data)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (tag) DO UPDATE SET
+             address_tag = excluded.address_tag,
+             status = excluded.status,
+             timestamp = excluded.timestamp,
+             data = excluded.data",
+        params![
+            tag.as_slice(),
+            address_tag.as_slice(),
+            entry.status.as_str(),
+            entry.timestamp,
+            data
+        ],
+    )?;
+    Ok(())
+}
+
+/// Decrypt a note row, taking the status and block number from their columns
+fn decrypt_note(
+    cipher: &WalletCipher,
+    tag: &[u8],
+    status: NoteStatus,
+    block_number: Option<u64>,
+    data: &[u8],
+) -> Result<Commitment> {
+    let plaintext = cipher.decrypt(tag, data)?;
+    let mut commitment: Commitment = serde_json::from_slice(&plaintext)?;
+    commitment.status = status;
+    commitment.block_number = block_number;
+    Ok(commitment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{MnemonicPhrase, SpendingKey};
+
+    const TEST_PASSWORD: &str = "test password";
+
+    fn note(commitment: u64, address: &Field) -> Commitment {
+        Commitment {
+            blinding: Field::from(commitment + 100),
+            block_number: Some(commitment),
+            ..Commitment::test_note(commitment, *address, 10 * commitment as u128)
+        }
+    }
+
+    #[test]
+    fn test_notes_persist_across_opens() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let path = path.to_str().unwrap();
+        let address = Field::from(0xabcdu64);
+
+        let mut store = SqliteStore::open(path, TEST_PASSWORD).unwrap();
+        let mut keyring = Keyring::new();
+        keyring.mnemonic = Some(MnemonicPhrase::new("test phrase".to_string()));
+        store.save_keyring(&keyring).unwrap();
+        keyring.next_account_index = 3;
+        store
+            .apply(NoteBatch {
+                keyring: Some(keyring),
+                insert: vec![note(1, &address), note(2, &address), note(3, &Field::from(1u64))],
+                ..Default::default()
+            })
+            .unwrap();
+        drop(store);
+
+        let store = SqliteStore::open(path, TEST_PASSWORD).unwrap();
+        let keyring = store.load_keyring().unwrap();
+        assert_eq!(keyring.mnemonic, Some(MnemonicPhrase::new("test phrase".to_string())));
+        assert_eq!(keyring.next_account_index, 3);
+        assert_eq!(store.commitments(&address, NoteStatus::Confirmed).unwrap().len(), 2);
+        let stored = store.get_commitment(&Field::from(2u64)).unwrap().unwrap();
+        assert_eq!(stored.balance, 20);
+        assert_eq!(stored.blinding, Field::from(102u64));
+        assert_eq!(store.commitments_since_block(2).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_status_is_indexed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let mut store = SqliteStore::open(path.to_str().unwrap(), TEST_PASSWORD).unwrap();
+        let address = Field::from(0xabcdu64);
+        store
+            .apply(NoteBatch {
+                insert: vec![note(1, &address), note(2, &address)],
+                ..Default::default()
+            })
+            .unwrap();
+
+        // A transfer spends note 1 and creates note 3 in one batch
+        let mut input = note(1, &address);
+        input.status = NoteStatus::PendingSpend;
+        let mut output = note(3, &address);
+        output.status = NoteStatus::PendingOutput;
+        store
+            .apply(NoteBatch {
+                insert: vec![input, output],
+                remove: vec![Field::from(2u64)],
+                ..Default::default()
+            })
+            .unwrap();
+
+        let confirmed = store.commitments(&address, NoteStatus::Confirmed).unwrap();
+        assert!(confirmed.is_empty());
+        let mut pending: Vec<_> = store
+            .pending_commitments()
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.nonce, c.status))
+            .collect();
+        pending.sort_by_key(|(nonce, _)| *nonce);
+        assert_eq!(
+            pending,
+            vec![(1, NoteStatus::PendingSpend), (3, NoteStatus::PendingOutput)]
+        );
+        assert!(store.get_commitment(&Field::from(2u64)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_version_1_schema_is_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let path = path.to_str().unwrap();
+        let address = Field::from(0xabcdu64);
+
+        // A database as written by the first version of this store
+        let cipher = WalletCipher::new(TEST_PASSWORD).unwrap();
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE meta (key TEXT PRIMARY KEY, value BLOB NOT NULL);
+             CREATE TABLE notes (tag BLOB PRIMARY KEY, address_tag BLOB NOT NULL,
+                 spent INTEGER NOT NULL DEFAULT 0, block_number INTEGER, data BLOB NOT NULL);
+             CREATE INDEX notes_by_address ON notes (address_tag, spent);
+             CREATE INDEX notes_by_spent ON notes (spent);
+             CREATE INDEX notes_by_block ON notes (block_number);
+             PRAGMA user_version = 1;",
+        )
+        .unwrap();
+        write_meta(&conn, &cipher, &Keyring::new()).unwrap();
+        for (commitment, spent) in [(1u64, true), (2, false)] {
+            let note = note(commitment, &address);
+            let tag = cipher.index_tag(note.commitment.as_bytes());
+            let data = cipher.encrypt(&tag, &serde_json::to_vec(&note).unwrap()).unwrap();
+            conn.execute(
+                "INSERT INTO notes (tag, address_tag, spent, data) VALUES (?1, ?2, ?3, ?4)",
+                params![tag.as_slice(), cipher.index_tag(address.as_bytes()).as_slice(), spent, data],
+            )
+            .unwrap();
+        }
+        drop(conn);
+
+        let steps = SqliteStore::plan_migration(path, TEST_PASSWORD).unwrap();
+        assert_eq!(steps.iter().map(|step| step.from).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(
+            steps[0].changes,
+            vec![
+                "set status = confirmed on 1 note(s)".to_string(),
+                "set status = spent on 1 note(s)".to_string(),
+                "drop spent".to_string(),
+            ]
+        );
+        assert_eq!(steps[1].changes, vec!["add an empty history table".to_string()]);
+
+        // A backup left by an interrupted attempt is replaced
+        let backup = store::migration_backup_path(path, 1);
+        std::fs::write(&backup, b"stale").unwrap();
+
+        let store = SqliteStore::open(path, TEST_PASSWORD).unwrap();
+        let status = |n: u64| store.get_commitment(&Field::from(n)).unwrap().unwrap().status;
+        assert_eq!(status(1), NoteStatus::Spent);
+        assert_eq!(status(2), NoteStatus::Confirmed);
+        assert!(store.history(None).unwrap().is_empty());
+        let conn = Connection::open(&backup).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+        drop(conn);
+        assert!(SqliteStore::plan_migration(path, TEST_PASSWORD).is_err());
+        drop(store);
+        assert!(SqliteStore::plan_migration(path, TEST_PASSWORD).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_database_is_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let path = path.to_str().unwrap();
+        let secret = SpendingKey::generate();
+        let address = secret.address();
+
+        let mut store = SqliteStore::open(path, TEST_PASSWORD).unwrap();
+        let mut keyring = Keyring::new();
+        keyring.accounts.insert(address, secret.clone());
+        store.save_keyring(&keyring).unwrap();
+        store
+            .apply(NoteBatch {
+                insert: vec![note(1, &address)],
+                ..Default::default()
+            })
+            .unwrap();
+        drop(store);
+
+        let data = std::fs::read(path).unwrap();
+        for value in [secret.expose_secret(), &address] {
+            let needle = value.as_bytes();
+            assert!(!data.windows(needle.len()).any(|w| w == needle));
+            let needle = value.to_hex();
+            assert!(!data.windows(needle.len()).any(|w| w == needle.as_bytes()));
+        }
+
+        assert!(matches!(
+            SqliteStore::open(path, "wrong password"),
+            Err(ClientError::InvalidPassword)
+        ));
+    }
+
+    #[test]
+    fn test_change_password_rewrites_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let path = path.to_str().unwrap();
+        let address = Field::from(0xabcdu64);
+
+        let mut store = SqliteStore::open(path, TEST_PASSWORD).unwrap();
+        store
+            .apply(NoteBatch {
+                insert: vec![note(1, &address)],
+                ..Default::default()
+            })
+            .unwrap();
+        store.change_password("new password").unwrap();
+        assert_eq!(store.commitments(&address, NoteStatus::Confirmed).unwrap().len(), 1);
+        drop(store);
+
+        assert!(SqliteStore::open(path, TEST_PASSWORD).is_err());
+        let store = SqliteStore::open(path, "new password").unwrap();
+        assert_eq!(store.commitments(&address, NoteStatus::Confirmed).unwrap().len(), 1);
+    }
+}
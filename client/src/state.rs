@@ -1,212 +1,1379 @@
 //! Local state management for private token balances and commitments
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
-use std::path::Path;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
 
+use crate::backup::{Backup, RestoreReport};
+use crate::crypto::{self, HashScheme};
 use crate::error::{ClientError, Result};
+use crate::field::Field;
+use crate::history::{self, HistoryEntry, OperationKind, OperationStatus};
+use crate::keys::{self, MnemonicPhrase, PaymentAddress, SpendingKey, ViewingKey};
+use crate::note::{self, EncryptedNote};
+use crate::selection::{self, CoinSelector};
+use crate::store::{self, NoteBatch, WalletStore};
 
 /// Represents a single UTXO commitment
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Commitment {
     /// The commitment hash
-    pub commitment: String,
+    pub commitment: Field,
     /// The derived address (from secret)
-    pub address: String,
+    pub address: Field,
     /// The balance held in this commitment
     pub balance: u128,
     /// The nonce used in this commitment
     pub nonce: u64,
-    /// The secret key (stored encrypted in production)
-    pub secret: String,
-    /// Whether this commitment has been spent
-    pub spent: bool,
+    /// The random blinding that hides this commitment
+    #[serde(default)]
+    pub blinding: Field,
+    /// The hash scheme this commitment was computed with
+    #[serde(default)]
+    pub scheme: HashScheme,
+    /// The spending key, if this wallet owns the commitment
+    #[serde(default, deserialize_with = "keys::deserialize_optional_spending_key")]
+    pub secret: Option<SpendingKey>,
+    /// Where the note is in its lifecycle
+    #[serde(default)]
+    pub status: NoteStatus,
+    /// Transaction that created the note, if this wallet submitted it
+    #[serde(default)]
+    pub created_by: Option<String>,
+    /// Transaction that spends the note
+    #[serde(default)]
+    pub spent_by: Option<String>,
+    /// Block the commitment was seen in on-chain, if known
+    #[serde(default)]
+    pub block_number: Option<u64>,
+}
+
+impl Commitment {
+    /// Whether two copies of a note hold the same note, whatever their states
+    pub fn same_note(&self, other: &Commitment) -> bool {
+        (self.commitment, self.address, self.balance, self.nonce, self.blinding, self.scheme)
+            == (other.commitment, other.address, other.balance, other.nonce, other.blinding, other.scheme)
+    }
+
+    /// Whether the note can be used as a transfer input
+    pub fn is_spendable(&self) -> bool {
+        self.status == NoteStatus::Confirmed
+    }
+
+    /// The transaction the note is waiting on, if any
+    pub fn pending_transaction(&self) -> Option<&str> {
+        match self.status {
+            NoteStatus::PendingOutput => self.created_by.as_deref(),
+            NoteStatus::PendingSpend => self.spent_by.as_deref(),
+            NoteStatus::Confirmed | NoteStatus::Spent | NoteStatus::Failed => None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Commitment {
+    /// A confirmed Pedersen note with nonce `id` and no blinding, key or transaction
+    ///
+    /// Tests override the fields they care about with struct update syntax.
+    pub(crate) fn test_note(id: u64, address: Field, balance: u128) -> Self {
+        Self {
+            commitment: Field::from(id),
+            address,
+            balance,
+            nonce: id,
+            blinding: Field::ZERO,
+            scheme: HashScheme::Pedersen,
+            secret: None,
+            status: NoteStatus::Confirmed,
+            created_by: None,
+            spent_by: None,
+            block_number: None,
+        }
+    }
+}
+
+/// Lifecycle of a note
+///
+/// Outputs start as `PendingOutput` and become `Confirmed` once their transaction
+/// is mined. Spending a confirmed note moves it to `PendingSpend` and then `Spent`.
+/// If the transaction fails its outputs become `Failed` and its inputs go back to
+/// `Confirmed`. Failed outputs are kept, since a transaction given up on may still
+/// be mined, and are confirmed again if their note turns up on-chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteStatus {
+    /// Created by a transaction that hasn't been mined yet
+    PendingOutput,
+    /// On-chain and spendable
+    #[default]
+    Confirmed,
+    /// Spent by a transaction that hasn't been mined yet
+    PendingSpend,
+    /// Spent by a mined transaction
+    Spent,
+    /// Created by a transaction that failed or was given up on
+    Failed,
+}
+
+impl NoteStatus {
+    /// Every status
+    pub const ALL: [NoteStatus; 5] = [
+        NoteStatus::PendingOutput,
+        NoteStatus::Confirmed,
+        NoteStatus::PendingSpend,
+        NoteStatus::Spent,
+        NoteStatus::Failed,
+    ];
+
+    /// Name used in the state file and the CLI
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NoteStatus::PendingOutput => "pending_output",
+            NoteStatus::Confirmed => "confirmed",
+            NoteStatus::PendingSpend => "pending_spend",
+            NoteStatus::Spent => "spent",
+            NoteStatus::Failed => "failed",
+        }
+    }
+
+    /// Whether the note is waiting on a transaction
+    pub fn is_pending(self) -> bool {
+        matches!(self, NoteStatus::PendingOutput | NoteStatus::PendingSpend)
+    }
+}
+
+impl fmt::Display for NoteStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for NoteStatus {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        NoteStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| ClientError::InvalidInput(format!("Unknown note status: {}", s)))
+    }
+}
+
+/// Keys and wallet metadata, kept in memory while the wallet is open
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keyring {
+    /// Known addresses and their spending keys
+    pub accounts: HashMap<Field, SpendingKey>,
+    /// View-only addresses and their viewing keys
+    #[serde(default)]
+    pub view_only: HashMap<Field, ViewingKey>,
+    /// Mnemonic phrase that HD accounts are derived from
+    #[serde(default)]
+    pub mnemonic: Option<MnemonicPhrase>,
+    /// Index of the next HD account to derive
+    #[serde(default)]
+    pub next_account_index: u32,
+    /// Unique labels of accounts, by address
+    #[serde(default)]
+    pub labels: HashMap<Field, String>,
+    /// Hash construction version the stored addresses and commitments were derived with
+    #[serde(default = "legacy_hash_version")]
+    pub hash_version: u32,
+}
+
+/// State files written before the hash version was recorded
+fn legacy_hash_version() -> u32 {
+    1
+}
+
+impl Keyring {
+    /// An empty keyring for the current hash version
+    pub fn new() -> Self {
+        Self {
+            accounts: HashMap::new(),
+            view_only: HashMap::new(),
+            mnemonic: None,
+            next_account_index: 0,
+            labels: HashMap::new(),
+            hash_version: crypto::HASH_VERSION,
+        }
+    }
+
+    /// Refuse keys derived with a different hash construction
+    pub fn check_hash_version(&self) -> Result<()> {
+        if self.hash_version != crypto::HASH_VERSION {
+            return Err(ClientError::HashVersionMismatch {
+                found: self.hash_version,
+                expected: crypto::HASH_VERSION,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Keyring {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Manages local private state
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug)]
 pub struct StateManager {
-    /// Path to the state file
-    #[serde(skip)]
-    state_file: String,
-    /// Map of commitment hash to commitment data
-    commitments: HashMap<String, Commitment>,
-    /// Known addresses and their secrets
-    accounts: HashMap<String, String>,
+    /// Where the wallet is persisted
+    store: Box<dyn WalletStore>,
+    /// Keys and wallet metadata
+    keyring: Keyring,
 }
 
 impl StateManager {
-    /// Create a new state manager
-    pub fn new(state_file: &str) -> Result<Self> {
-        let mut manager = Self {
-            state_file: state_file.to_string(),
-            commitments: HashMap::new(),
-            accounts: HashMap::new(),
-        };
-        
-        // Load existing state if file exists
-        if Path::new(state_file).exists() {
-            manager.load()?;
+    /// Whether a wallet has already been written to `state_file`
+    pub fn exists(state_file: &str) -> bool {
+        store::store_exists(state_file)
+    }
+
+    /// Whether the wallet at `state_file` predates encryption, so opening it
+    /// encrypts it under a new password
+    pub fn is_unencrypted(state_file: &str) -> bool {
+        store::is_unencrypted(state_file)
+    }
+
+    /// Unlock the wallet at `state_file` with its password, or start a new one
+    /// that will be encrypted under `password`
+    ///
+    /// The backend is chosen from the file extension (see [`store::open_store`]).
+    /// The wallet stays locked against other processes until the manager is dropped.
+    pub fn new(state_file: &str, password: &str) -> Result<Self> {
+        let store = store::open_store(state_file, password)?;
+        let keyring = store.load_keyring()?;
+        Ok(Self { store, keyring })
+    }
+
+    /// Save the keyring
+    pub fn save(&mut self) -> Result<()> {
+        self.store.save_keyring(&self.keyring)
+    }
+
+    /// Re-encrypt the wallet under a new password
+    pub fn change_password(&mut self, new_password: &str) -> Result<()> {
+        self.store.change_password(new_password)
+    }
+
+    /// Add a new account (address -> secret mapping)
+    pub fn add_account(&mut self, address: Field, secret: SpendingKey) -> Result<()> {
+        self.keyring.view_only.remove(&address);
+        self.keyring.accounts.insert(address, secret);
+        self.save()
+    }
+
+    /// Add an account derived from the wallet mnemonic at the given index
+    pub fn add_derived_account(
+        &mut self,
+        index: u32,
+        address: Field,
+        secret: SpendingKey,
+    ) -> Result<()> {
+        self.keyring.view_only.remove(&address);
+        self.keyring.accounts.insert(address, secret);
+        self.keyring.next_account_index = self.keyring.next_account_index.max(index + 1);
+        self.save()
+    }
+
+    /// Add a view-only account from its viewing key, returning its address
+    pub fn add_viewing_key(&mut self, viewing_key: ViewingKey) -> Result<Field> {
+        let address = viewing_key.address();
+        if !self.keyring.accounts.contains_key(&address) {
+            self.keyring.view_only.insert(address, viewing_key);
+            self.save()?;
         }
-        
-        Ok(manager)
+        Ok(address)
     }
 
-    /// Load state from file
-    fn load(&mut self) -> Result<()> {
-        let data = fs::read_to_string(&self.state_file)?;
-        let loaded: StateManager = serde_json::from_str(&data)?;
-        self.commitments = loaded.commitments;
-        self.accounts = loaded.accounts;
-        Ok(())
+    /// Get the viewing key for an address, derived from its secret if spendable
+    pub fn get_viewing_key(&self, address: &Field) -> Option<ViewingKey> {
+        self.keyring
+            .accounts
+            .get(address)
+            .map(ViewingKey::from_spending_key)
+            .or_else(|| self.keyring.view_only.get(address).copied())
     }
 
-    /// Save state to file
-    pub fn save(&self) -> Result<()> {
-        let data = serde_json::to_string_pretty(self)?;
-        fs::write(&self.state_file, data)?;
+    /// Whether an address is tracked without its secret
+    pub fn is_view_only(&self, address: &Field) -> bool {
+        self.keyring.view_only.contains_key(address)
+    }
+
+    /// Get the mnemonic phrase HD accounts are derived from
+    pub fn get_mnemonic(&self) -> Option<&MnemonicPhrase> {
+        self.keyring.mnemonic.as_ref()
+    }
+
+    /// Set the wallet mnemonic, refusing to replace a different one
+    pub fn set_mnemonic(&mut self, mnemonic: MnemonicPhrase) -> Result<()> {
+        match &self.keyring.mnemonic {
+            Some(existing) if *existing != mnemonic => Err(ClientError::StateError(
+                "Wallet already has a different mnemonic".to_string(),
+            )),
+            _ => {
+                self.keyring.mnemonic = Some(mnemonic);
+                self.save()
+            }
+        }
+    }
+
+    /// Index of the next HD account to derive
+    pub fn next_account_index(&self) -> u32 {
+        self.keyring.next_account_index
+    }
+
+    /// Check that a label is well-formed and not used by another account
+    ///
+    /// Labels can't start with `0x`, so they never shadow an address.
+    pub fn check_label(&self, label: &str) -> Result<()> {
+        if label.is_empty() || label.trim() != label {
+            return Err(ClientError::InvalidInput(
+                "Labels must not be empty or start or end with whitespace".to_string(),
+            ));
+        }
+        if label.starts_with("0x") || label.starts_with(keys::PAYMENT_ADDRESS_PREFIX) {
+            return Err(ClientError::InvalidInput(format!(
+                "Label {} looks like an address",
+                label
+            )));
+        }
+        if self.keyring.labels.values().any(|existing| existing == label) {
+            return Err(ClientError::InvalidInput(format!(
+                "Label {} is already in use",
+                label
+            )));
+        }
         Ok(())
     }
 
-    /// Add a new account (address -> secret mapping)
-    pub fn add_account(&mut self, address: String, secret: String) -> Result<()> {
-        self.accounts.insert(address, secret);
+    /// Label an account, replacing any label it already has
+    pub fn set_label(&mut self, address: &Field, label: &str) -> Result<()> {
+        if self.get_label(address) == Some(label) {
+            return Ok(());
+        }
+        if self.get_viewing_key(address).is_none() {
+            return Err(ClientError::InvalidInput(format!("Unknown account: {}", address)));
+        }
+        self.check_label(label)?;
+        self.keyring.labels.insert(*address, label.to_string());
         self.save()
     }
 
+    /// Delete a label, returning the address it belonged to
+    pub fn remove_label(&mut self, label: &str) -> Result<Field> {
+        let address = self.labelled(label).ok_or_else(|| {
+            ClientError::InvalidInput(format!("No account is labelled {}", label))
+        })?;
+        self.keyring.labels.remove(&address);
+        self.save()?;
+        Ok(address)
+    }
+
+    /// Get the label of an account
+    pub fn get_label(&self, address: &Field) -> Option<&str> {
+        self.keyring.labels.get(address).map(String::as_str)
+    }
+
+    /// Address of the account with a label
+    fn labelled(&self, label: &str) -> Option<Field> {
+        self.keyring
+            .labels
+            .iter()
+            .find(|(_, existing)| *existing == label)
+            .map(|(address, _)| *address)
+    }
+
+    /// Resolve an account given by its label or its hex address
+    pub fn resolve_account(&self, account: &str) -> Result<Field> {
+        if let Some(address) = self.labelled(account) {
+            return Ok(address);
+        }
+        crypto::hex_to_field(account).map_err(|e| {
+            if account.starts_with("0x") {
+                e
+            } else {
+                ClientError::InvalidInput(format!("No account is labelled {}", account))
+            }
+        })
+    }
+
+    /// Resolve a recipient given by its payment address, or by the label or hex
+    /// address of an account this wallet can view
+    ///
+    /// A payment address for an account the wallet knows must carry that account's
+    /// encryption key.
+    pub fn resolve_recipient(&self, recipient: &str) -> Result<PaymentAddress> {
+        if recipient.starts_with(keys::PAYMENT_ADDRESS_PREFIX) {
+            let payment: PaymentAddress = recipient.parse()?;
+            match self.get_viewing_key(&payment.address) {
+                Some(viewing_key) if viewing_key.payment_address() != payment => {
+                    Err(ClientError::InvalidInput(format!(
+                        "Payment address {} does not carry the encryption key of {}",
+                        recipient, payment.address
+                    )))
+                }
+                _ => Ok(payment),
+            }
+        } else {
+            let address = self.resolve_account(recipient)?;
+            self.get_viewing_key(&address)
+                .map(|viewing_key| viewing_key.payment_address())
+                .ok_or_else(|| {
+                    ClientError::InvalidInput(format!(
+                        "Unknown recipient {}, pass their payment address",
+                        recipient
+                    ))
+                })
+        }
+    }
+
     /// Get secret for an address
-    pub fn get_secret(&self, address: &str) -> Option<&String> {
-        self.accounts.get(address)
+    pub fn get_secret(&self, address: &Field) -> Option<&SpendingKey> {
+        self.keyring.accounts.get(address)
     }
 
     /// Add a new commitment
     pub fn add_commitment(&mut self, commitment: Commitment) -> Result<()> {
-        self.commitments.insert(commitment.commitment.clone(), commitment);
-        self.save()
+        self.store.apply(NoteBatch {
+            insert: vec![commitment],
+            ..Default::default()
+        })
     }
 
     /// Get a commitment by hash
-    pub fn get_commitment(&self, commitment_hash: &str) -> Option<&Commitment> {
-        self.commitments.get(commitment_hash)
+    pub fn get_commitment(&self, commitment_hash: &Field) -> Result<Option<Commitment>> {
+        self.store.get_commitment(commitment_hash)
     }
 
-    /// Get all unspent commitments for an address
-    pub fn get_unspent_commitments(&self, address: &str) -> Vec<&Commitment> {
-        self.commitments
-            .values()
-            .filter(|c| c.address == address && !c.spent)
-            .collect()
+    /// Get all spendable commitments for an address
+    pub fn get_unspent_commitments(&self, address: &Field) -> Result<Vec<Commitment>> {
+        self.store.commitments(address, NoteStatus::Confirmed)
     }
 
-    /// Mark a commitment as spent
-    pub fn mark_spent(&mut self, commitment_hash: &str) -> Result<()> {
-        if let Some(commitment) = self.commitments.get_mut(commitment_hash) {
-            commitment.spent = true;
-            self.save()?;
-            Ok(())
-        } else {
-            Err(ClientError::CommitmentNotFound(commitment_hash.to_string()))
+    /// Get an address's commitments in a given state
+    pub fn get_commitments_with_status(
+        &self,
+        address: &Field,
+        status: NoteStatus,
+    ) -> Result<Vec<Commitment>> {
+        self.store.commitments(address, status)
+    }
+
+    /// Get commitments seen on-chain at or after a block
+    pub fn commitments_since_block(&self, block_number: u64) -> Result<Vec<Commitment>> {
+        self.store.commitments_since_block(block_number)
+    }
+
+    /// Mark a commitment as spent outside of a tracked transaction
+    pub fn mark_spent(&mut self, commitment_hash: &Field) -> Result<()> {
+        let mut commitment = self
+            .get_commitment(commitment_hash)?
+            .ok_or_else(|| ClientError::CommitmentNotFound(commitment_hash.to_hex()))?;
+        commitment.status = NoteStatus::Spent;
+        self.add_commitment(commitment)
+    }
+
+    /// Record a submitted transaction that spends `inputs` and creates `outputs`
+    ///
+    /// The inputs become pending spends and the outputs pending outputs until the
+    /// transaction is confirmed or fails, and `history` is recorded as pending with
+    /// it. Nothing is written if an input is unknown or not spendable.
+    pub fn record_transaction(
+        &mut self,
+        tx_hash: &str,
+        inputs: &[Field],
+        outputs: Vec<Commitment>,
+        history: Vec<HistoryEntry>,
+    ) -> Result<()> {
+        let mut batch = NoteBatch::default();
+        for hash in inputs {
+            let mut input = self
+                .get_commitment(hash)?
+                .ok_or_else(|| ClientError::CommitmentNotFound(hash.to_hex()))?;
+            if !input.is_spendable() {
+                return Err(ClientError::StateError(format!(
+                    "Commitment {} is {} and cannot be spent",
+                    hash, input.status
+                )));
+            }
+            input.status = NoteStatus::PendingSpend;
+            input.spent_by = Some(tx_hash.to_string());
+            batch.insert.push(input);
+        }
+        for mut output in outputs {
+            output.status = NoteStatus::PendingOutput;
+            output.created_by = Some(tx_hash.to_string());
+            batch.insert.push(output);
         }
+        for mut entry in history {
+            entry.status = OperationStatus::Pending;
+            entry.tx_hash = Some(tx_hash.to_string());
+            batch.history.push(entry);
+        }
+        self.store.apply(batch)
+    }
+
+    /// Hashes of transactions that notes are waiting on
+    pub fn pending_transactions(&self) -> Result<BTreeSet<String>> {
+        Ok(self
+            .store
+            .pending_commitments()?
+            .iter()
+            .filter_map(|c| c.pending_transaction().map(str::to_string))
+            .collect())
+    }
+
+    /// Settle a mined transaction: its inputs become spent and its outputs spendable
+    pub fn confirm_transaction(&mut self, tx_hash: &str, block_number: Option<u64>) -> Result<()> {
+        let mut batch = NoteBatch::default();
+        for mut commitment in self.transaction_notes(tx_hash)? {
+            commitment.status = match commitment.status {
+                NoteStatus::PendingSpend => NoteStatus::Spent,
+                _ => {
+                    commitment.block_number = block_number.or(commitment.block_number);
+                    NoteStatus::Confirmed
+                }
+            };
+            batch.insert.push(commitment);
+        }
+        for mut entry in self.transaction_history(tx_hash)? {
+            entry.status = OperationStatus::Confirmed;
+            entry.block_number = block_number.or(entry.block_number);
+            batch.history.push(entry);
+        }
+        self.store.apply(batch)
+    }
+
+    /// Roll back a failed or dropped transaction
+    ///
+    /// Its outputs are marked failed and its inputs become spendable again.
+    pub fn fail_transaction(&mut self, tx_hash: &str) -> Result<()> {
+        let mut batch = NoteBatch::default();
+        for mut commitment in self.transaction_notes(tx_hash)? {
+            if commitment.status == NoteStatus::PendingOutput {
+                commitment.status = NoteStatus::Failed;
+            } else {
+                commitment.status = NoteStatus::Confirmed;
+                commitment.spent_by = None;
+            }
+            batch.insert.push(commitment);
+        }
+        for mut entry in self.transaction_history(tx_hash)? {
+            entry.status = OperationStatus::Failed;
+            batch.history.push(entry);
+        }
+        self.store.apply(batch)
+    }
+
+    /// Pending notes waiting on a transaction
+    fn transaction_notes(&self, tx_hash: &str) -> Result<Vec<Commitment>> {
+        let notes: Vec<Commitment> = self
+            .store
+            .pending_commitments()?
+            .into_iter()
+            .filter(|c| c.pending_transaction() == Some(tx_hash))
+            .collect();
+        if notes.is_empty() {
+            return Err(ClientError::StateError(format!(
+                "No pending notes for transaction {}",
+                tx_hash
+            )));
+        }
+        Ok(notes)
+    }
+
+    /// When a pending transaction was recorded, as a Unix timestamp
+    pub fn transaction_recorded_at(&self, tx_hash: &str) -> Result<Option<u64>> {
+        Ok(self
+            .transaction_history(tx_hash)?
+            .iter()
+            .map(|entry| entry.timestamp)
+            .min())
+    }
+
+    /// Pending history entries for a transaction
+    fn transaction_history(&self, tx_hash: &str) -> Result<Vec<HistoryEntry>> {
+        Ok(self
+            .store
+            .pending_history()?
+            .into_iter()
+            .filter(|entry| entry.tx_hash.as_deref() == Some(tx_hash))
+            .collect())
+    }
+
+    /// Operations recorded for an address, or for every account, oldest first
+    pub fn history(&self, address: Option<&Field>) -> Result<Vec<HistoryEntry>> {
+        self.store.history(address)
     }
 
     /// Get total balance for an address
-    pub fn get_balance(&self, address: &str) -> u128 {
-        self.get_unspent_commitments(address)
+    pub fn get_balance(&self, address: &Field) -> Result<u128> {
+        Ok(self
+            .get_unspent_commitments(address)?
             .iter()
             .map(|c| c.balance)
-            .sum()
+            .sum())
     }
 
-    /// Find a suitable commitment for spending
-    pub fn find_spendable_commitment(&self, address: &str, amount: u128) -> Option<&Commitment> {
-        self.get_unspent_commitments(address)
-            .into_iter()
-            .find(|c| c.balance >= amount)
+    /// Trial-decrypt encrypted notes with every known viewing key
+    ///
+    /// Notes that decrypt and open their commitment for one of our addresses are
+    /// added to the state as confirmed, including outputs of transactions that were
+    /// given up on but mined after all. Notes received get a receive in the history;
+    /// change is already accounted for by the send that created it. Returns the
+    /// commitments that were discovered.
+    pub fn scan_encrypted_notes(&mut self, notes: &[EncryptedNote]) -> Result<Vec<Field>> {
+        let mut discovered = Vec::new();
+        let mut received = Vec::new();
+        for (commitment, is_change) in self.open_notes(notes) {
+            let known = discovered.iter().any(|c: &Commitment| c.commitment == commitment.commitment)
+                || self
+                    .store
+                    .get_commitment(&commitment.commitment)?
+                    .is_some_and(|c| c.status != NoteStatus::Failed);
+            if !known {
+                if !is_change {
+                    received.push(commitment.commitment);
+                }
+                discovered.push(commitment);
+            }
+        }
+
+        let hashes = discovered.iter().map(|c| c.commitment).collect();
+        if !discovered.is_empty() {
+            let history = discovered
+                .iter()
+                .filter(|c| received.contains(&c.commitment))
+                .map(|c| {
+                    let mut entry =
+                        HistoryEntry::new(OperationKind::Receive, c.commitment, c.address, c.balance);
+                    entry.block_number = c.block_number;
+                    entry.created = vec![c.commitment];
+                    entry
+                })
+                .collect();
+            self.store.apply(NoteBatch {
+                insert: discovered,
+                history,
+                ..Default::default()
+            })?;
+        }
+        Ok(hashes)
+    }
+
+    /// Trial-decrypt encrypted notes without storing them
+    ///
+    /// Returns a confirmed commitment for each note addressed to one of our accounts,
+    /// or holding the change of one of their transfers.
+    pub fn decrypt_notes(&self, notes: &[EncryptedNote]) -> Vec<Commitment> {
+        self.open_notes(notes).into_iter().map(|(c, _)| c).collect()
     }
 
-    /// Get all accounts
-    pub fn list_accounts(&self) -> Vec<(&String, u128)> {
-        self.accounts
+    /// Trial-decrypt encrypted notes as received notes and as change, flagging change
+    ///
+    /// Empty notes, such as the change of a transfer that spent a whole note, are
+    /// skipped.
+    fn open_notes(&self, notes: &[EncryptedNote]) -> Vec<(Commitment, bool)> {
+        let viewing_keys: Vec<(Field, ViewingKey)> = self
+            .keyring
+            .accounts
             .keys()
-            .map(|addr| (addr, self.get_balance(addr)))
+            .chain(self.keyring.view_only.keys())
+            .filter_map(|address| Some((*address, self.get_viewing_key(address)?)))
+            .collect();
+
+        let mut discovered = Vec::new();
+        for encrypted in notes {
+            for (address, viewing_key) in &viewing_keys {
+                let opened = note::try_decrypt_note(viewing_key, encrypted)
+                    .map(|plaintext| (plaintext, false))
+                    .or_else(|| {
+                        note::try_decrypt_change(viewing_key, encrypted)
+                            .map(|plaintext| (plaintext, true))
+                    });
+                let Some((plaintext, is_change)) = opened else {
+                    continue;
+                };
+                if plaintext.amount == 0 {
+                    break;
+                }
+                // The note doesn't say which scheme the sender used, so try each
+                let Some(scheme) = HashScheme::ALL.into_iter().find(|&scheme| {
+                    let expected = crypto::compute_commitment(
+                        scheme,
+                        address,
+                        plaintext.amount,
+                        plaintext.nonce,
+                        &plaintext.blinding,
+                    );
+                    expected == encrypted.commitment
+                }) else {
+                    continue;
+                };
+
+                let commitment = Commitment {
+                    commitment: encrypted.commitment,
+                    address: *address,
+                    balance: plaintext.amount,
+                    nonce: plaintext.nonce,
+                    blinding: plaintext.blinding,
+                    scheme,
+                    secret: self.keyring.accounts.get(address).cloned(),
+                    status: NoteStatus::Confirmed,
+                    created_by: None,
+                    spent_by: None,
+                    block_number: encrypted.block_number,
+                };
+                discovered.push((commitment, is_change));
+                break;
+            }
+        }
+        discovered
+    }
+
+    /// Nullifiers of every confirmed note, each with its commitment
+    ///
+    /// The nullifiers are computed with the viewing key, so notes of view-only
+    /// accounts are included and can be marked spent once their nullifier is used
+    /// on-chain.
+    pub fn confirmed_nullifiers(&self) -> Result<Vec<(Field, Field)>> {
+        let mut nullifiers = Vec::new();
+        for address in self.keyring.accounts.keys().chain(self.keyring.view_only.keys()) {
+            let Some(viewing_key) = self.get_viewing_key(address) else {
+                continue;
+            };
+            for note in self.get_unspent_commitments(address)? {
+                let nullifier = viewing_key.nullifier(note.scheme, note.nonce, &note.blinding);
+                nullifiers.push((nullifier, note.commitment));
+            }
+        }
+        Ok(nullifiers)
+    }
+
+    /// Choose the note an address spends to send `amount`
+    ///
+    /// See [`selection::select_note`] for the errors.
+    pub fn select_commitment(
+        &self,
+        address: &Field,
+        amount: u128,
+        selector: &dyn CoinSelector,
+    ) -> Result<Commitment> {
+        selection::select_note(selector, &self.get_unspent_commitments(address)?, amount)
+    }
+
+    /// Get all accounts, including view-only ones
+    pub fn list_accounts(&self) -> Result<Vec<(Field, u128)>> {
+        self.keyring
+            .accounts
+            .keys()
+            .chain(self.keyring.view_only.keys())
+            .map(|addr| Ok((*addr, self.get_balance(addr)?)))
             .collect()
     }
+
+    /// Snapshot the keys, metadata, notes and history for a backup
+    pub fn backup(&self) -> Result<Backup> {
+        Ok(Backup {
+            created_at: history::unix_time(),
+            keyring: self.keyring.clone(),
+            commitments: self.store.all_commitments()?,
+            history: self.store.history(None)?,
+        })
+    }
+
+    /// Merge a backup into the wallet
+    ///
+    /// Accounts, notes, history and labels the wallet lacks are added, and notes the
+    /// backup shows as spent are marked spent. Anything else the wallet already has
+    /// is kept. A note whose contents differ from the wallet's copy, or a different
+    /// mnemonic, is a conflict, and then nothing is written.
+    pub fn restore_backup(&mut self, backup: Backup) -> Result<RestoreReport> {
+        backup.verify()?;
+        let mut report = RestoreReport::default();
+        let mut conflicts = Vec::new();
+        let mut keyring = self.keyring.clone();
+
+        match (&keyring.mnemonic, backup.keyring.mnemonic) {
+            (Some(ours), Some(theirs)) if *ours != theirs => {
+                conflicts.push("the backup has a different mnemonic".to_string());
+            }
+            (None, theirs) => keyring.mnemonic = theirs,
+            _ => {}
+        }
+        keyring.next_account_index =
+            keyring.next_account_index.max(backup.keyring.next_account_index);
+        for (address, secret) in backup.keyring.accounts {
+            if !keyring.accounts.contains_key(&address) {
+                keyring.view_only.remove(&address);
+                keyring.accounts.insert(address, secret);
+                report.accounts_added += 1;
+            }
+        }
+        for (address, viewing_key) in backup.keyring.view_only {
+            if !keyring.accounts.contains_key(&address)
+                && !keyring.view_only.contains_key(&address)
+            {
+                keyring.view_only.insert(address, viewing_key);
+                report.accounts_added += 1;
+            }
+        }
+        for (address, label) in backup.keyring.labels {
+            let taken = keyring.labels.values().any(|existing| *existing == label);
+            match keyring.labels.get(&address) {
+                Some(existing) if *existing == label => {}
+                None if !taken => {
+                    keyring.labels.insert(address, label);
+                }
+                _ => report.labels_skipped.push(label),
+            }
+        }
+
+        let mut batch = NoteBatch::default();
+        for note in backup.commitments {
+            match self.store.get_commitment(&note.commitment)? {
+                None => {
+                    batch.insert.push(note);
+                    report.notes_added += 1;
+                }
+                Some(existing) if !existing.same_note(&note) => {
+                    conflicts.push(format!(
+                        "note {} differs from the wallet's copy",
+                        note.commitment
+                    ));
+                }
+                // A note can't become unspent, so the backup is newer
+                Some(existing)
+                    if existing.status == NoteStatus::Confirmed
+                        && note.status == NoteStatus::Spent =>
+                {
+                    batch.insert.push(note);
+                    report.notes_spent += 1;
+                }
+                Some(existing) => {
+                    if existing.status != note.status {
+                        report.notes_kept += 1;
+                    }
+                }
+            }
+        }
+
+        let known: HashSet<(OperationKind, Field)> = self
+            .store
+            .history(None)?
+            .iter()
+            .map(|entry| (entry.kind, entry.id))
+            .collect();
+        for entry in backup.history {
+            if !known.contains(&(entry.kind, entry.id)) {
+                batch.history.push(entry);
+                report.history_added += 1;
+            }
+        }
+
+        if !conflicts.is_empty() {
+            return Err(ClientError::BackupConflict(conflicts));
+        }
+        batch.keyring = Some(keyring.clone());
+        self.store.apply(batch)?;
+        self.keyring = keyring;
+        Ok(report)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
     use tempfile::NamedTempFile;
 
+    const TEST_PASSWORD: &str = "test password";
+
+    fn phrase(words: &str) -> MnemonicPhrase {
+        MnemonicPhrase::new(words.to_string())
+    }
+
     #[test]
     fn test_add_and_get_commitment() {
         let temp_file = NamedTempFile::new().unwrap();
-        let mut manager = StateManager::new(temp_file.path().to_str().unwrap()).unwrap();
-
-        let commitment = Commitment {
-            commitment: "0x1234".to_string(),
-            address: "0xabcd".to_string(),
-            balance: 100,
-            nonce: 1,
-            secret: "0xsecret".to_string(),
-            spent: false,
-        };
+        let path = temp_file.path().to_str().unwrap();
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+
+        let commitment = Commitment::test_note(0x1234, Field::from(0xabcdu64), 100);
 
         manager.add_commitment(commitment.clone()).unwrap();
         
-        let retrieved = manager.get_commitment("0x1234").unwrap();
+        let retrieved = manager.get_commitment(&Field::from(0x1234u64)).unwrap().unwrap();
         assert_eq!(retrieved.balance, 100);
     }
 
     #[test]
     fn test_balance_calculation() {
         let temp_file = NamedTempFile::new().unwrap();
-        let mut manager = StateManager::new(temp_file.path().to_str().unwrap()).unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
 
-        let address = "0xabcd".to_string();
+        let address = Field::from(0xabcdu64);
         
-        manager.add_commitment(Commitment {
-            commitment: "0x1".to_string(),
-            address: address.clone(),
-            balance: 100,
-            nonce: 1,
-            secret: "0xsecret".to_string(),
-            spent: false,
-        }).unwrap();
-
-        manager.add_commitment(Commitment {
-            commitment: "0x2".to_string(),
-            address: address.clone(),
-            balance: 50,
-            nonce: 2,
-            secret: "0xsecret".to_string(),
-            spent: false,
-        }).unwrap();
-
-        assert_eq!(manager.get_balance(&address), 150);
+        manager.add_commitment(Commitment::test_note(1, address, 100)).unwrap();
+        manager.add_commitment(Commitment::test_note(2, address, 50)).unwrap();
+
+        assert_eq!(manager.get_balance(&address).unwrap(), 150);
     }
 
     #[test]
     fn test_mark_spent() {
         let temp_file = NamedTempFile::new().unwrap();
-        let mut manager = StateManager::new(temp_file.path().to_str().unwrap()).unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
 
-        let address = "0xabcd".to_string();
+        let address = Field::from(0xabcdu64);
         
-        manager.add_commitment(Commitment {
-            commitment: "0x1".to_string(),
-            address: address.clone(),
-            balance: 100,
-            nonce: 1,
-            secret: "0xsecret".to_string(),
-            spent: false,
-        }).unwrap();
-
-        assert_eq!(manager.get_balance(&address), 100);
+        manager.add_commitment(Commitment::test_note(1, address, 100)).unwrap();
+
+        assert_eq!(manager.get_balance(&address).unwrap(), 100);
         
-        manager.mark_spent("0x1").unwrap();
+        manager.mark_spent(&Field::from(1u64)).unwrap();
         
-        assert_eq!(manager.get_balance(&address), 0);
+        assert_eq!(manager.get_balance(&address).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_derived_accounts_persist() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+
+        manager.set_mnemonic(phrase("test phrase")).unwrap();
+        let secret = SpendingKey::from_field(Field::from(1u64));
+        manager
+            .add_derived_account(2, Field::from(0xabcdu64), secret.clone())
+            .unwrap();
+        assert!(manager.set_mnemonic(phrase("other phrase")).is_err());
+        drop(manager);
+
+        let reloaded = StateManager::new(path, TEST_PASSWORD).unwrap();
+        assert_eq!(reloaded.get_mnemonic(), Some(&phrase("test phrase")));
+        assert!(!format!("{:?}", reloaded).contains("test phrase"));
+        assert_eq!(reloaded.next_account_index(), 3);
+        assert_eq!(reloaded.get_secret(&Field::from(0xabcdu64)), Some(&secret));
+    }
+
+    #[test]
+    fn test_state_file_is_encrypted() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let secret = SpendingKey::generate();
+        let address = secret.address();
+        StateManager::new(path, TEST_PASSWORD)
+            .unwrap()
+            .add_account(address, secret.clone())
+            .unwrap();
+
+        let data = fs::read_to_string(path).unwrap();
+        assert!(!data.contains(&secret.expose_secret().to_hex()));
+        assert!(!data.contains(&address.to_hex()));
+
+        let reloaded = StateManager::new(path, TEST_PASSWORD).unwrap();
+        assert_eq!(reloaded.get_secret(&address), Some(&secret));
+        drop(reloaded);
+        assert!(matches!(
+            StateManager::new(path, "wrong password"),
+            Err(ClientError::InvalidPassword)
+        ));
+    }
+
+    #[test]
+    fn test_change_password() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+        manager.set_mnemonic(phrase("test phrase")).unwrap();
+        manager.change_password("new password").unwrap();
+        drop(manager);
+
+        assert!(StateManager::new(path, TEST_PASSWORD).is_err());
+        let reloaded = StateManager::new(path, "new password").unwrap();
+        assert_eq!(reloaded.get_mnemonic(), Some(&phrase("test phrase")));
+    }
+
+    #[test]
+    fn test_plaintext_state_is_encrypted_on_unlock() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let plaintext = serde_json::json!({
+            "commitments": {},
+            "accounts": {},
+            "mnemonic": "test phrase",
+            "hash_version": crypto::HASH_VERSION,
+        });
+        fs::write(path, plaintext.to_string()).unwrap();
+
+        StateManager::new(path, TEST_PASSWORD).unwrap();
+        assert!(!fs::read_to_string(path).unwrap().contains("test phrase"));
+        let reloaded = StateManager::new(path, TEST_PASSWORD).unwrap();
+        assert_eq!(reloaded.get_mnemonic(), Some(&phrase("test phrase")));
+    }
+
+    #[test]
+    fn test_rejects_other_hash_version() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        StateManager::new(path, TEST_PASSWORD).unwrap().save().unwrap();
+        assert!(StateManager::new(path, TEST_PASSWORD).is_ok());
+
+        // State from before the hash version was recorded
+        fs::write(path, r#"{"commitments": {}, "accounts": {}}"#).unwrap();
+        assert!(matches!(
+            StateManager::new(path, TEST_PASSWORD),
+            Err(ClientError::HashVersionMismatch { found: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_transaction_lifecycle_with_each_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["state.json", "state.db"] {
+            let path = dir.path().join(file);
+            let path = path.to_str().unwrap();
+            let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+            let address = Field::from(0xabcdu64);
+            let note = |commitment: u64, balance: u128| Commitment::test_note(commitment, address, balance);
+
+            // A send of 25 from note 1, with its change in note 3 and the recipient's note
+            // identified by `recipient`
+            let send = |recipient: u64| {
+                let mut entry =
+                    HistoryEntry::new(OperationKind::Send, Field::from(recipient), address, 25);
+                entry.consumed = vec![Field::from(1u64)];
+                entry
+            };
+
+            let mint = HistoryEntry::new(OperationKind::Mint, Field::from(1u64), address, 100);
+            manager.record_transaction("0xmint", &[], vec![note(1, 100)], vec![mint]).unwrap();
+            assert_eq!(manager.get_balance(&address).unwrap(), 0);
+            manager.confirm_transaction("0xmint", Some(10)).unwrap();
+            assert_eq!(manager.get_balance(&address).unwrap(), 100);
+
+            // A failed transfer marks its outputs failed and frees its input
+            let input = Field::from(1u64);
+            manager
+                .record_transaction("0xfail", &[input], vec![note(2, 75)], vec![send(20)])
+                .unwrap();
+            assert!(manager
+                .record_transaction("0xdup", &[input], Vec::new(), Vec::new())
+                .is_err());
+            assert_eq!(
+                manager.pending_transactions().unwrap().into_iter().collect::<Vec<_>>(),
+                vec!["0xfail".to_string()]
+            );
+            assert!(manager.transaction_recorded_at("0xfail").unwrap().is_some());
+            manager.fail_transaction("0xfail").unwrap();
+            let failed = manager.get_commitment(&Field::from(2u64)).unwrap().unwrap();
+            assert_eq!(failed.status, NoteStatus::Failed);
+            assert_eq!(manager.get_balance(&address).unwrap(), 100);
+            assert!(manager.pending_transactions().unwrap().is_empty());
+
+            manager
+                .record_transaction("0xsend", &[input], vec![note(3, 75)], vec![send(30)])
+                .unwrap();
+            drop(manager);
+
+            // Pending state survives a restart
+            let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+            manager.confirm_transaction("0xsend", Some(12)).unwrap();
+            assert!(manager.pending_transactions().unwrap().is_empty());
+            assert_eq!(manager.get_balance(&address).unwrap(), 75);
+            let spent = manager.get_commitment(&input).unwrap().unwrap();
+            assert_eq!(spent.status, NoteStatus::Spent);
+            assert_eq!(spent.spent_by.as_deref(), Some("0xsend"));
+            let output = manager.get_commitment(&Field::from(3u64)).unwrap().unwrap();
+            assert_eq!(output.block_number, Some(12));
+
+            // The history has every attempt, and its settled entries add up to the balance
+            let history = manager.history(Some(&address)).unwrap();
+            let summary: Vec<_> = history
+                .iter()
+                .map(|e| (e.kind, e.status, e.tx_hash.as_deref(), e.block_number))
+                .collect();
+            assert_eq!(
+                summary,
+                vec![
+                    (OperationKind::Mint, OperationStatus::Confirmed, Some("0xmint"), Some(10)),
+                    (OperationKind::Send, OperationStatus::Failed, Some("0xfail"), None),
+                    (OperationKind::Send, OperationStatus::Confirmed, Some("0xsend"), Some(12)),
+                ]
+            );
+            let net: i128 = history
+                .iter()
+                .filter(|e| e.status == OperationStatus::Confirmed)
+                .map(|e| if e.kind.is_credit() { e.amount as i128 } else { -(e.amount as i128) })
+                .sum();
+            assert_eq!(net, 75);
+            assert!(manager.history(Some(&Field::from(1u64))).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_account_labels() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+
+        let (alice_key, bob_key) = (SpendingKey::generate(), SpendingKey::generate());
+        let (alice, bob) = (alice_key.address(), bob_key.address());
+        let (alice_view, bob_view) = (alice_key.viewing_key(), bob_key.viewing_key());
+        manager.add_account(alice, alice_key).unwrap();
+        manager.add_account(bob, bob_key).unwrap();
+        manager.set_label(&alice, "alice").unwrap();
+        manager.set_label(&bob, "bob").unwrap();
+
+        assert!(manager.set_label(&bob, "alice").is_err());
+        assert!(manager.set_label(&Field::from(1u64), "carol").is_err());
+        for bad in ["", " alice", "0xcafe", "ptk1cafe"] {
+            assert!(manager.check_label(bad).is_err());
+        }
+        drop(manager);
+
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+        assert_eq!(manager.resolve_account("alice").unwrap(), alice);
+        assert_eq!(manager.resolve_account(&bob.to_hex()).unwrap(), bob);
+        assert!(manager.resolve_account("carol").is_err());
+
+        // Recipients resolve to payment addresses, which must match known accounts
+        let alice_payment = alice_view.payment_address();
+        assert_eq!(manager.resolve_recipient("alice").unwrap(), alice_payment);
+        let given = alice_payment.to_string();
+        assert_eq!(manager.resolve_recipient(&given).unwrap(), alice_payment);
+        let mismatched = PaymentAddress {
+            encryption_key: bob_view.encryption_key(),
+            ..alice_payment
+        };
+        assert!(manager.resolve_recipient(&mismatched.to_string()).is_err());
+        let stranger = SpendingKey::generate().viewing_key().payment_address();
+        assert_eq!(manager.resolve_recipient(&stranger.to_string()).unwrap(), stranger);
+        assert!(manager.resolve_recipient(&stranger.address.to_hex()).is_err());
+
+        // Renaming frees the old label
+        manager.set_label(&alice, "alice-savings").unwrap();
+        assert_eq!(manager.get_label(&alice), Some("alice-savings"));
+        assert!(manager.resolve_account("alice").is_err());
+        manager.set_label(&bob, "alice").unwrap();
+
+        assert_eq!(manager.remove_label("alice").unwrap(), bob);
+        assert_eq!(manager.get_label(&bob), None);
+        assert!(manager.remove_label("alice").is_err());
+    }
+
+    #[test]
+    fn test_restore_backup_merges() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |file: &str| dir.path().join(file).to_str().unwrap().to_string();
+        let mut source = StateManager::new(&path("source.json"), TEST_PASSWORD).unwrap();
+        let mut target = StateManager::new(&path("target.db"), TEST_PASSWORD).unwrap();
+
+        let secret = SpendingKey::generate();
+        let address = secret.address();
+        source.add_account(address, secret.clone()).unwrap();
+        source.set_label(&address, "alice").unwrap();
+        let blinding = crypto::generate_blinding();
+        let hash = crypto::compute_commitment(HashScheme::Pedersen, &address, 60, 1, &blinding);
+        let note = Commitment {
+            commitment: hash,
+            blinding,
+            secret: Some(secret),
+            ..Commitment::test_note(1, address, 60)
+        };
+        let mint = HistoryEntry::new(OperationKind::Mint, hash, address, 60);
+        source.record_transaction("0xmint", &[], vec![note.clone()], vec![mint]).unwrap();
+        source.confirm_transaction("0xmint", Some(4)).unwrap();
+
+        // The target already uses the label for one of its own accounts
+        let own = SpendingKey::generate();
+        let own_address = own.address();
+        target.add_account(own_address, own).unwrap();
+        target.set_label(&own_address, "alice").unwrap();
+
+        let report = target.restore_backup(source.backup().unwrap()).unwrap();
+        assert_eq!(
+            (report.accounts_added, report.notes_added, report.history_added),
+            (1, 1, 1)
+        );
+        assert_eq!(report.labels_skipped, vec!["alice".to_string()]);
+        assert_eq!(target.get_balance(&address).unwrap(), 60);
+        assert_eq!(target.history(Some(&address)).unwrap()[0].block_number, Some(4));
+
+        // Restoring again changes nothing, and a later spend is picked up
+        let report = target.restore_backup(source.backup().unwrap()).unwrap();
+        assert_eq!(
+            RestoreReport {
+                labels_skipped: Vec::new(),
+                ..report
+            },
+            RestoreReport::default()
+        );
+        source.mark_spent(&hash).unwrap();
+        let report = target.restore_backup(source.backup().unwrap()).unwrap();
+        assert_eq!(report.notes_spent, 1);
+        assert_eq!(target.get_balance(&address).unwrap(), 0);
+
+        // A wallet whose copy of the note disagrees takes nothing from the backup
+        let mut other = StateManager::new(&path("other.json"), TEST_PASSWORD).unwrap();
+        other.add_commitment(Commitment { balance: 6_000, ..note }).unwrap();
+        other.set_mnemonic(phrase("other phrase")).unwrap();
+        source.set_mnemonic(phrase("source phrase")).unwrap();
+        match other.restore_backup(source.backup().unwrap()) {
+            Err(ClientError::BackupConflict(conflicts)) => assert_eq!(conflicts.len(), 2),
+            result => panic!("expected a conflict, got {:?}", result),
+        }
+        assert!(other.get_secret(&address).is_none());
+    }
+
+    #[test]
+    fn test_view_only_account() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+
+        let secret = SpendingKey::generate();
+        let viewing_key = secret.viewing_key();
+        let address = manager.add_viewing_key(viewing_key).unwrap();
+        drop(manager);
+
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+        assert!(manager.is_view_only(&address));
+        assert_eq!(manager.get_secret(&address), None);
+        assert_eq!(manager.get_viewing_key(&address), Some(viewing_key));
+        assert_eq!(manager.list_accounts().unwrap().len(), 1);
+
+        // Importing the secret upgrades the account to spendable
+        manager.add_account(address, secret).unwrap();
+        assert!(!manager.is_view_only(&address));
+        assert_eq!(manager.get_viewing_key(&address), Some(viewing_key));
+    }
+
+    #[test]
+    fn test_scan_encrypted_notes() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+
+        let secret = SpendingKey::generate();
+        let address = secret.address();
+        manager.add_account(address, secret.clone()).unwrap();
+
+        let plaintext = note::NotePlaintext {
+            amount: 25,
+            nonce: 0,
+            blinding: crypto::generate_blinding(),
+        };
+        let commitment =
+            crypto::compute_commitment(HashScheme::Poseidon2, &address, 25, 0, &plaintext.blinding);
+        let encryption_key = secret.viewing_key().encryption_key();
+        let ours = EncryptedNote {
+            commitment,
+            ciphertext: note::encrypt_note(&encryption_key, &commitment, &plaintext).unwrap(),
+            block_number: Some(7),
+        };
+
+        let other_key = SpendingKey::generate().viewing_key();
+        let other_commitment = Field::from(1u64);
+        let theirs = EncryptedNote {
+            commitment: other_commitment,
+            ciphertext: note::encrypt_note(
+                &other_key.encryption_key(),
+                &other_commitment,
+                &plaintext,
+            )
+            .unwrap(),
+            block_number: Some(7),
+        };
+
+        // The note was the output of a transaction that was given up on, but mined
+        manager
+            .add_commitment(Commitment {
+                commitment,
+                status: NoteStatus::Failed,
+                ..Commitment::test_note(0, address, 25)
+            })
+            .unwrap();
+        assert_eq!(manager.get_balance(&address).unwrap(), 0);
+
+        let discovered = manager.scan_encrypted_notes(&[ours.clone(), theirs]).unwrap();
+        assert_eq!(discovered, vec![commitment]);
+        assert_eq!(manager.get_balance(&address).unwrap(), 25);
+        let stored = manager.get_commitment(&commitment).unwrap().unwrap();
+        assert_eq!(stored.status, NoteStatus::Confirmed);
+        assert_eq!(stored.secret, Some(secret));
+        assert_eq!(stored.blinding, plaintext.blinding);
+        assert_eq!(stored.scheme, HashScheme::Poseidon2);
+        assert_eq!(stored.block_number, Some(7));
+
+        let history = manager.history(None).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].kind, OperationKind::Receive);
+        assert_eq!((history[0].id, history[0].amount), (commitment, 25));
+        assert_eq!(history[0].block_number, Some(7));
+
+        // Rescanning the same note is a no-op
+        assert!(manager.scan_encrypted_notes(&[ours]).unwrap().is_empty());
+        assert_eq!(manager.history(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_view_only_account_follows_change() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let mut manager = StateManager::new(path, TEST_PASSWORD).unwrap();
+
+        let secret = SpendingKey::generate();
+        let viewing_key = secret.viewing_key();
+        let address = manager.add_viewing_key(viewing_key).unwrap();
+
+        let change = |amount| {
+            let plaintext = note::NotePlaintext {
+                amount,
+                nonce: 1,
+                blinding: crypto::generate_blinding(),
+            };
+            let commitment = crypto::compute_commitment(
+                HashScheme::Poseidon2,
+                &address,
+                amount,
+                1,
+                &plaintext.blinding,
+            );
+            let encrypted = EncryptedNote {
+                commitment,
+                ciphertext: note::encrypt_note(&viewing_key.change_key(), &commitment, &plaintext)
+                    .unwrap(),
+                block_number: Some(9),
+            };
+            (encrypted, plaintext)
+        };
+        let (ours, plaintext) = change(40);
+        let (empty, _) = change(0);
+
+        // Change is stored without a receive, and empty change is skipped
+        let discovered = manager.scan_encrypted_notes(&[ours.clone(), empty]).unwrap();
+        assert_eq!(discovered, vec![ours.commitment]);
+        assert_eq!(manager.get_balance(&address).unwrap(), 40);
+        assert!(manager.history(None).unwrap().is_empty());
+
+        // Its nullifier is the one the spending key reveals when it is spent
+        let nullifier =
+            secret.viewing_key().nullifier(HashScheme::Poseidon2, 1, &plaintext.blinding);
+        assert_eq!(
+            manager.confirmed_nullifiers().unwrap(),
+            vec![(nullifier, ours.commitment)]
+        );
+        manager.mark_spent(&ours.commitment).unwrap();
+        assert_eq!(manager.get_balance(&address).unwrap(), 0);
+        assert!(manager.confirmed_nullifiers().unwrap().is_empty());
     }
 }
@@ -0,0 +1,713 @@
+//! Storage backends for wallet state
+//!
+//! `StateManager` keeps the keyring in memory and reads and writes notes through a
+//! `WalletStore`. The JSON store keeps the whole wallet in one encrypted file and
+//! rewrites it on every change. The SQLite store keeps one encrypted row per note,
+//! so a change only touches the rows it affects.
+
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+use crate::error::{ClientError, Result};
+use crate::field::Field;
+use crate::history::{HistoryEntry, OperationStatus};
+use crate::migrate::{self, MigrationStep};
+use crate::sqlite::SqliteStore;
+use crate::state::{Commitment, Keyring, NoteStatus};
+use crate::vault::{EncryptedWallet, WalletCipher};
+
+/// Suffix of the last complete copy of the state file
+pub(crate) const BACKUP_SUFFIX: &str = "bak";
+
+/// Suffix of the temporary file a save is written to before being renamed
+pub(crate) const TEMP_SUFFIX: &str = "tmp";
+
+/// Suffix a corrupt state file is moved to after recovery
+const CORRUPT_SUFFIX: &str = "corrupt";
+
+/// Suffix the original of an unencrypted wallet is kept at when it is first encrypted
+const PLAINTEXT_SUFFIX: &str = "plaintext.bak";
+
+/// Suffix of the advisory lock file
+const LOCK_SUFFIX: &str = "lock";
+
+/// File extensions that select the SQLite store
+const SQLITE_EXTENSIONS: [&str; 3] = ["db", "sqlite", "sqlite3"];
+
+/// Keyring, note and history changes that are applied together or not at all
+#[derive(Debug, Default)]
+pub struct NoteBatch {
+    /// Replacement keys and wallet metadata, if they change
+    pub keyring: Option<Keyring>,
+    /// New or updated notes, replacing any stored note with the same commitment
+    pub insert: Vec<Commitment>,
+    /// Commitments of notes to delete
+    pub remove: Vec<Field>,
+    /// New or updated history entries, replacing any stored entry with the same kind and id
+    pub history: Vec<HistoryEntry>,
+}
+
+/// Persistent storage for a wallet
+pub trait WalletStore: fmt::Debug {
+    /// Load the keys and wallet metadata
+    fn load_keyring(&self) -> Result<Keyring>;
+
+    /// Replace the stored keys and wallet metadata
+    fn save_keyring(&mut self, keyring: &Keyring) -> Result<()>;
+
+    /// Look up a note by its commitment
+    fn get_commitment(&self, commitment: &Field) -> Result<Option<Commitment>>;
+
+    /// Notes owned by an address that are in a given state
+    fn commitments(&self, address: &Field, status: NoteStatus) -> Result<Vec<Commitment>>;
+
+    /// Notes waiting on a transaction, for every address
+    fn pending_commitments(&self) -> Result<Vec<Commitment>>;
+
+    /// Notes first seen on-chain at or after a block
+    fn commitments_since_block(&self, block_number: u64) -> Result<Vec<Commitment>>;
+
+    /// Every stored note, in any state
+    fn all_commitments(&self) -> Result<Vec<Commitment>>;
+
+    /// History entries for an address, or for every address, oldest first
+    fn history(&self, address: Option<&Field>) -> Result<Vec<HistoryEntry>>;
+
+    /// History entries whose transaction hasn't settled
+    fn pending_history(&self) -> Result<Vec<HistoryEntry>>;
+
+    /// Apply a batch of keyring and note changes atomically
+    fn apply(&mut self, batch: NoteBatch) -> Result<()>;
+
+    /// Re-encrypt everything under a new password
+    fn change_password(&mut self, password: &str) -> Result<()>;
+}
+
+/// Open the store for a wallet path
+///
+/// Paths ending in `.db`, `.sqlite` or `.sqlite3` use the SQLite store, anything
+/// else the JSON store.
+pub fn open_store(path: &str, password: &str) -> Result<Box<dyn WalletStore>> {
+    if is_sqlite_path(path) {
+        Ok(Box::new(SqliteStore::open(path, password)?))
+    } else {
+        Ok(Box::new(JsonStore::open(path, password)?))
+    }
+}
+
+/// Report the migrations opening a wallet would apply, without changing it
+pub fn plan_migration(path: &str, password: &str) -> Result<Vec<MigrationStep>> {
+    if is_sqlite_path(path) {
+        return SqliteStore::plan_migration(path, password);
+    }
+    let _lock = lock_state_file(path)?;
+    let loaded = match JsonStore::read_state(path, password) {
+        Err(ClientError::CorruptState(_)) => {
+            JsonStore::read_state(&sibling_path(path, BACKUP_SUFFIX), password)?
+        }
+        result => result?,
+    };
+    Ok(loaded.migrations)
+}
+
+/// Where the state is kept before it is migrated from `version`
+pub fn migration_backup_path(state_file: &str, version: u32) -> String {
+    sibling_path(state_file, &format!("v{}.{}", version, BACKUP_SUFFIX))
+}
+
+/// Whether a wallet path selects the SQLite store
+fn is_sqlite_path(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SQLITE_EXTENSIONS.contains(&ext))
+}
+
+/// Whether a wallet has already been written to `path`
+pub fn store_exists(path: &str) -> bool {
+    let non_empty = |path: &str| fs::metadata(path).map(|m| m.len() > 0).unwrap_or(false);
+    non_empty(path) || non_empty(&sibling_path(path, BACKUP_SUFFIX))
+}
+
+/// Whether the wallet at `path` is an unencrypted file from an older client
+///
+/// Opening it encrypts it under the password given, so that password should be
+/// confirmed first.
+pub fn is_unencrypted(path: &str) -> bool {
+    if is_sqlite_path(path) {
+        return false;
+    }
+    let Ok(data) = fs::read_to_string(path).map(Zeroizing::new) else {
+        return false;
+    };
+    serde_json::from_str::<serde_json::Value>(&data).is_ok()
+        && serde_json::from_str::<EncryptedWallet>(&data).is_err()
+}
+
+/// The decrypted contents of a JSON wallet file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WalletFile {
+    /// State format version, see [`migrate::STATE_VERSION`]
+    version: u32,
+    /// Map of commitment hash to commitment data
+    commitments: HashMap<Field, Commitment>,
+    /// History entries in the order they were recorded
+    history: Vec<HistoryEntry>,
+    /// Keys and wallet metadata
+    #[serde(flatten)]
+    keyring: Keyring,
+}
+
+/// Wallet kept in a single encrypted JSON file
+///
+/// Saves are atomic and the previous complete copy is kept as a backup.
+#[derive(Debug)]
+pub struct JsonStore {
+    /// Path to the state file
+    state_file: String,
+    /// The decrypted wallet
+    wallet: WalletFile,
+    /// Password-derived key the state file is sealed with
+    cipher: WalletCipher,
+    /// Advisory lock on the wallet, released when the store is dropped
+    _lock: File,
+}
+
+impl JsonStore {
+    /// Unlock the wallet at `state_file`, or start a new one encrypted under `password`
+    pub fn open(state_file: &str, password: &str) -> Result<Self> {
+        let lock = lock_state_file(state_file)?;
+
+        if !store_exists(state_file) {
+            let store = Self {
+                state_file: state_file.to_string(),
+                wallet: WalletFile {
+                    version: migrate::STATE_VERSION,
+                    commitments: HashMap::new(),
+                    history: Vec::new(),
+                    keyring: Keyring::new(),
+                },
+                cipher: WalletCipher::new(password)?,
+                _lock: lock,
+            };
+            return Ok(store);
+        }
+
+        let (loaded, recovered) = match Self::read_state(state_file, password) {
+            Err(ClientError::CorruptState(reason)) => {
+                let backup = sibling_path(state_file, BACKUP_SUFFIX);
+                let loaded = Self::read_state(&backup, password)
+                    .map_err(|_| ClientError::CorruptState(reason.clone()))?;
+                tracing::warn!("{}; recovered wallet from {}", reason, backup);
+
+                // Keep the damaged file around for inspection
+                if Path::new(state_file).exists() {
+                    fs::rename(state_file, sibling_path(state_file, CORRUPT_SUFFIX))?;
+                }
+                (loaded, true)
+            }
+            result => (result?, false),
+        };
+
+        // Keep the unencrypted original until the password it is sealed under is known
+        // to work
+        if loaded.legacy {
+            let original = sibling_path(state_file, PLAINTEXT_SUFFIX);
+            write_private(&original, &loaded.original)?;
+            tracing::warn!(
+                "Encrypting the wallet; its unencrypted original is kept at {} until you delete it",
+                original
+            );
+        }
+
+        // Keep the state as it was before the first migration step
+        if let Some(first) = loaded.migrations.first() {
+            let backup = migration_backup_path(state_file, first.from);
+            let sealed = loaded.cipher.seal(&loaded.original)?;
+            write_atomic(&backup, serde_json::to_string_pretty(&sealed)?.as_bytes())?;
+            for step in &loaded.migrations {
+                tracing::info!("Migrated state from version {} to {}", step.from, step.to);
+            }
+        }
+
+        let needs_save = recovered || loaded.legacy || !loaded.migrations.is_empty();
+        let store = Self {
+            state_file: state_file.to_string(),
+            wallet: loaded.wallet,
+            cipher: loaded.cipher,
+            _lock: lock,
+        };
+        if needs_save {
+            store.save()?;
+        }
+        Ok(store)
+    }
+
+    /// Read, decrypt and migrate a state file
+    fn read_state(path: &str, password: &str) -> Result<LoadedWallet> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => Zeroizing::new(data),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(ClientError::CorruptState(format!("{} is missing", path)));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if data.trim().is_empty() {
+            return Err(ClientError::CorruptState(format!("{} is empty", path)));
+        }
+
+        let (cipher, plaintext, legacy) = match serde_json::from_str::<EncryptedWallet>(&data) {
+            Ok(wallet) => {
+                let (cipher, plaintext) = WalletCipher::unlock(&wallet, password)?;
+                (cipher, plaintext, false)
+            }
+            // Plaintext wallets from older clients are encrypted on first unlock
+            Err(_) => {
+                let plaintext = Zeroizing::new(data.as_bytes().to_vec());
+                (WalletCipher::new(password)?, plaintext, true)
+            }
+        };
+
+        let unreadable = |e: serde_json::Error| {
+            ClientError::CorruptState(format!("{} is truncated or unreadable: {}", path, e))
+        };
+        let mut state: serde_json::Value = serde_json::from_slice(&plaintext).map_err(unreadable)?;
+        let migrations = migrate::migrate(&mut state)?;
+        let wallet: WalletFile = serde_json::from_value(state).map_err(unreadable)?;
+        wallet.keyring.check_hash_version()?;
+
+        Ok(LoadedWallet {
+            wallet,
+            cipher,
+            original: plaintext,
+            legacy,
+            migrations,
+        })
+    }
+
+    /// Encrypt and save the wallet
+    fn save(&self) -> Result<()> {
+        self.write(&self.wallet, &self.cipher)
+    }
+
+    /// Save a changed copy of the wallet, and keep it only once it is on disk
+    ///
+    /// A failed save leaves the wallet in memory as it is on disk.
+    fn update(&mut self, change: impl FnOnce(&mut WalletFile)) -> Result<()> {
+        let mut wallet = self.wallet.clone();
+        change(&mut wallet);
+        self.write(&wallet, &self.cipher)?;
+        self.wallet = wallet;
+        Ok(())
+    }
+
+    /// Encrypt `wallet` under `cipher` and write it to the state file
+    ///
+    /// Both the backup and the state file are replaced atomically, so a crash
+    /// leaves at least one complete copy on disk.
+    fn write(&self, wallet: &WalletFile, cipher: &WalletCipher) -> Result<()> {
+        let plaintext = Zeroizing::new(serde_json::to_vec(wallet)?);
+        let data = serde_json::to_string_pretty(&cipher.seal(&plaintext)?)?;
+        write_atomic(&sibling_path(&self.state_file, BACKUP_SUFFIX), data.as_bytes())?;
+        write_atomic(&self.state_file, data.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// A state file as read from disk
+struct LoadedWallet {
+    /// The migrated wallet
+    wallet: WalletFile,
+    /// Cipher for the wallet's password
+    cipher: WalletCipher,
+    /// Decrypted state exactly as it was stored
+    original: Zeroizing<Vec<u8>>,
+    /// Whether the file was unencrypted
+    legacy: bool,
+    /// Migration steps applied while reading
+    migrations: Vec<MigrationStep>,
+}
+
+impl WalletStore for JsonStore {
+    fn load_keyring(&self) -> Result<Keyring> {
+        Ok(self.wallet.keyring.clone())
+    }
+
+    fn save_keyring(&mut self, keyring: &Keyring) -> Result<()> {
+        self.update(|wallet| wallet.keyring = keyring.clone())
+    }
+
+    fn get_commitment(&self, commitment: &Field) -> Result<Option<Commitment>> {
+        Ok(self.wallet.commitments.get(commitment).cloned())
+    }
+
+    fn commitments(&self, address: &Field, status: NoteStatus) -> Result<Vec<Commitment>> {
+        Ok(self
+            .wallet
+            .commitments
+            .values()
+            .filter(|c| c.address == *address && c.status == status)
+            .cloned()
+            .collect())
+    }
+
+    fn pending_commitments(&self) -> Result<Vec<Commitment>> {
+        Ok(self
+            .wallet
+            .commitments
+            .values()
+            .filter(|c| c.status.is_pending())
+            .cloned()
+            .collect())
+    }
+
+    fn commitments_since_block(&self, block_number: u64) -> Result<Vec<Commitment>> {
+        Ok(self
+            .wallet
+            .commitments
+            .values()
+            .filter(|c| c.block_number.is_some_and(|block| block >= block_number))
+            .cloned()
+            .collect())
+    }
+
+    fn all_commitments(&self) -> Result<Vec<Commitment>> {
+        Ok(self.wallet.commitments.values().cloned().collect())
+    }
+
+    fn history(&self, address: Option<&Field>) -> Result<Vec<HistoryEntry>> {
+        let mut entries: Vec<HistoryEntry> = self
+            .wallet
+            .history
+            .iter()
+            .filter(|entry| address.is_none_or(|address| entry.address == *address))
+            .cloned()
+            .collect();
+        entries.sort_by_key(|entry| entry.timestamp);
+        Ok(entries)
+    }
+
+    fn pending_history(&self) -> Result<Vec<HistoryEntry>> {
+        Ok(self
+            .wallet
+            .history
+            .iter()
+            .filter(|entry| entry.status == OperationStatus::Pending)
+            .cloned()
+            .collect())
+    }
+
+    fn apply(&mut self, batch: NoteBatch) -> Result<()> {
+        self.update(|wallet| {
+            if let Some(keyring) = batch.keyring {
+                wallet.keyring = keyring;
+            }
+            for commitment in batch.insert {
+                wallet.commitments.insert(commitment.commitment, commitment);
+            }
+            for hash in &batch.remove {
+                wallet.commitments.remove(hash);
+            }
+            for entry in batch.history {
+                match wallet.history.iter_mut().find(|e| (e.kind, e.id) == (entry.kind, entry.id)) {
+                    Some(existing) => *existing = entry,
+                    None => wallet.history.push(entry),
+                }
+            }
+        })
+    }
+
+    fn change_password(&mut self, password: &str) -> Result<()> {
+        let cipher = WalletCipher::new(password)?;
+        self.write(&self.wallet, &cipher)?;
+        self.cipher = cipher;
+        Ok(())
+    }
+}
+
+/// Path of a file kept next to the state file, e.g. `private_state.json.bak`
+pub(crate) fn sibling_path(state_file: &str, suffix: &str) -> String {
+    format!("{}.{}", state_file, suffix)
+}
+
+/// Take the advisory lock for a wallet without blocking
+pub(crate) fn lock_state_file(state_file: &str) -> Result<File> {
+    let lock_path = sibling_path(state_file, LOCK_SUFFIX);
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)?;
+    lock.try_lock_exclusive().map_err(|e| {
+        if e.kind() == fs2::lock_contended_error().kind() {
+            ClientError::WalletLocked(state_file.to_string())
+        } else {
+            e.into()
+        }
+    })?;
+    Ok(lock)
+}
+
+/// Replace a file atomically: write a temporary file, fsync it, rename it over
+/// the target and fsync the directory
+pub(crate) fn write_atomic(path: &str, data: &[u8]) -> Result<()> {
+    let temp_path = sibling_path(path, TEMP_SUFFIX);
+    let mut temp = File::create(&temp_path)?;
+    temp.write_all(data)?;
+    temp.sync_all()?;
+    drop(temp);
+
+    fs::rename(&temp_path, path)?;
+
+    #[cfg(unix)]
+    {
+        let parent = Path::new(path)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Create a file only its owner can read, leaving an existing one as it is
+fn write_private(path: &str, data: &[u8]) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = match options.open(path) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(()),
+        result => result?,
+    };
+    file.write_all(data)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PASSWORD: &str = "test password";
+
+    fn note(commitment: u64, address: u64) -> Commitment {
+        Commitment {
+            block_number: Some(commitment),
+            ..Commitment::test_note(commitment, Field::from(address), 10)
+        }
+    }
+
+    #[test]
+    fn test_store_selected_by_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = dir.path().join("wallet.json");
+        let sqlite = dir.path().join("wallet.db");
+
+        let json_store = open_store(json.to_str().unwrap(), TEST_PASSWORD).unwrap();
+        let sqlite_store = open_store(sqlite.to_str().unwrap(), TEST_PASSWORD).unwrap();
+        assert!(format!("{:?}", json_store).starts_with("JsonStore"));
+        assert!(format!("{:?}", sqlite_store).starts_with("SqliteStore"));
+    }
+
+    #[test]
+    fn test_batch_inserts_and_removes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let mut store = JsonStore::open(path.to_str().unwrap(), TEST_PASSWORD).unwrap();
+        store
+            .apply(NoteBatch {
+                insert: vec![note(1, 0xa), note(2, 0xa)],
+                ..Default::default()
+            })
+            .unwrap();
+
+        let mut pending = note(3, 0xa);
+        pending.status = NoteStatus::PendingOutput;
+        let mut keyring = Keyring::new();
+        keyring.next_account_index = 2;
+        store
+            .apply(NoteBatch {
+                keyring: Some(keyring),
+                insert: vec![pending],
+                remove: vec![Field::from(1u64)],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(store.load_keyring().unwrap().next_account_index, 2);
+
+        let address = Field::from(0xau64);
+        let confirmed = store.commitments(&address, NoteStatus::Confirmed).unwrap();
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].commitment, Field::from(2u64));
+        let pending = store.pending_commitments().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].commitment, Field::from(3u64));
+    }
+
+    #[test]
+    fn test_failed_save_leaves_the_wallet_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let path = path.to_str().unwrap();
+        let mut store = JsonStore::open(path, TEST_PASSWORD).unwrap();
+        store
+            .apply(NoteBatch {
+                insert: vec![note(1, 0xa)],
+                ..Default::default()
+            })
+            .unwrap();
+
+        // A directory in the way of the backup's temporary file makes saves fail
+        let blocker = sibling_path(&sibling_path(path, BACKUP_SUFFIX), TEMP_SUFFIX);
+        fs::create_dir(&blocker).unwrap();
+        let mut keyring = Keyring::new();
+        keyring.next_account_index = 3;
+        assert!(store
+            .apply(NoteBatch {
+                keyring: Some(keyring.clone()),
+                remove: vec![Field::from(1u64)],
+                ..Default::default()
+            })
+            .is_err());
+        assert!(store.save_keyring(&keyring).is_err());
+        assert!(store.change_password("new password").is_err());
+
+        assert!(store.get_commitment(&Field::from(1u64)).unwrap().is_some());
+        assert_eq!(store.load_keyring().unwrap().next_account_index, 0);
+        fs::remove_dir(&blocker).unwrap();
+        store.save_keyring(&keyring).unwrap();
+        drop(store);
+        assert!(JsonStore::open(path, TEST_PASSWORD).is_ok());
+    }
+
+    #[test]
+    fn test_commitments_since_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let mut store = JsonStore::open(path.to_str().unwrap(), TEST_PASSWORD).unwrap();
+        store
+            .apply(NoteBatch {
+                insert: vec![note(1, 0xa), note(5, 0xa), note(9, 0xb)],
+                ..Default::default()
+            })
+            .unwrap();
+
+        let mut recent: Vec<u64> = store
+            .commitments_since_block(5)
+            .unwrap()
+            .iter()
+            .map(|c| c.nonce)
+            .collect();
+        recent.sort();
+        assert_eq!(recent, vec![5, 9]);
+    }
+
+    #[test]
+    fn test_unversioned_state_is_migrated_with_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let path = path.to_str().unwrap();
+
+        // An encrypted wallet from before the state version was recorded
+        let original = serde_json::json!({
+            "commitments": {
+                "0x01": { "commitment": "0x01", "address": "0x0a", "balance": 10, "nonce": 1,
+                          "blinding": "0x05", "scheme": "pedersen", "spent": false }
+            },
+            "accounts": {},
+            "hash_version": crate::crypto::HASH_VERSION,
+        });
+        let original = serde_json::to_vec(&original).unwrap();
+        let cipher = WalletCipher::new(TEST_PASSWORD).unwrap();
+        fs::write(path, serde_json::to_vec(&cipher.seal(&original).unwrap()).unwrap()).unwrap();
+
+        let steps = plan_migration(path, TEST_PASSWORD).unwrap();
+        assert_eq!(steps.first().map(|step| step.from), Some(0));
+        assert_eq!(steps.last().map(|step| step.to), Some(migrate::STATE_VERSION));
+        assert!(!Path::new(&migration_backup_path(path, 0)).exists());
+
+        let store = JsonStore::open(path, TEST_PASSWORD).unwrap();
+        assert_eq!(store.wallet.version, migrate::STATE_VERSION);
+        let note = store.get_commitment(&Field::from(1u64)).unwrap().unwrap();
+        assert_eq!((note.balance, note.block_number), (10, None));
+        drop(store);
+
+        let backup = fs::read_to_string(migration_backup_path(path, 0)).unwrap();
+        let backup: EncryptedWallet = serde_json::from_str(&backup).unwrap();
+        let (_, plaintext) = WalletCipher::unlock(&backup, TEST_PASSWORD).unwrap();
+        assert_eq!(*plaintext, original);
+        assert!(plan_migration(path, TEST_PASSWORD).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_plaintext_original_is_kept_when_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let path = path.to_str().unwrap();
+        let original = serde_json::json!({
+            "commitments": {},
+            "accounts": {},
+            "hash_version": crate::crypto::HASH_VERSION,
+        })
+        .to_string();
+        fs::write(path, &original).unwrap();
+        assert!(is_unencrypted(path));
+
+        drop(JsonStore::open(path, TEST_PASSWORD).unwrap());
+        assert!(!is_unencrypted(path));
+        let kept = sibling_path(path, PLAINTEXT_SUFFIX);
+        assert_eq!(fs::read_to_string(&kept).unwrap(), original);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&kept).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_wallet_is_locked_while_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let path = path.to_str().unwrap();
+        let store = JsonStore::open(path, TEST_PASSWORD).unwrap();
+
+        assert!(matches!(
+            JsonStore::open(path, TEST_PASSWORD),
+            Err(ClientError::WalletLocked(_))
+        ));
+        drop(store);
+        assert!(JsonStore::open(path, TEST_PASSWORD).is_ok());
+    }
+
+    #[test]
+    fn test_truncated_state_is_recovered_from_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let path = path.to_str().unwrap();
+        let mut store = JsonStore::open(path, TEST_PASSWORD).unwrap();
+        store
+            .apply(NoteBatch {
+                insert: vec![note(1, 0xa)],
+                ..Default::default()
+            })
+            .unwrap();
+        drop(store);
+
+        // Simulate a crash that left half of the file on disk
+        let data = fs::read(path).unwrap();
+        fs::write(path, &data[..data.len() / 2]).unwrap();
+
+        let recovered = JsonStore::open(path, TEST_PASSWORD).unwrap();
+        assert!(recovered.get_commitment(&Field::from(1u64)).unwrap().is_some());
+        assert!(Path::new(&sibling_path(path, CORRUPT_SUFFIX)).exists());
+        assert!(!Path::new(&sibling_path(path, TEMP_SUFFIX)).exists());
+        assert_eq!(fs::read(path).unwrap().len(), data.len());
+    }
+}
@@ -0,0 +1,316 @@
+//! Password-based encryption of the wallet file
+//!
+//! The serialized state is sealed with XChaCha20-Poly1305 under a key derived
+//! from the wallet password with Argon2id. The Argon2id output is only used as
+//! HKDF input keying material; the encryption key and the key that blinds record
+//! indexes are expanded from it with distinct info strings. The KDF parameters and
+//! salt are stored next to the ciphertext so they can be raised later without
+//! breaking old files.
+
+use std::fmt;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::error::{ClientError, Result};
+
+/// Current version of the encrypted wallet format
+const VAULT_VERSION: u32 = 1;
+
+/// Associated data binding ciphertexts to this file format
+const VAULT_AAD: &[u8] = b"private-token wallet v1";
+
+/// Salt size for the password KDF
+const SALT_LEN: usize = 16;
+
+/// Size of the XChaCha20 nonce prepended to encrypted records
+const NONCE_LEN: usize = 24;
+
+/// HKDF info string for the key that encrypts the wallet and its records
+const ENCRYPTION_KEY_INFO: &[u8] = b"private-token wallet encryption";
+
+/// HKDF info string for the key that blinds record index tags
+const INDEX_KEY_INFO: &[u8] = b"private-token wallet index";
+
+/// Argon2id memory cost in KiB
+#[cfg(not(test))]
+const ARGON2_M_COST: u32 = Params::DEFAULT_M_COST;
+#[cfg(test)]
+const ARGON2_M_COST: u32 = Params::MIN_M_COST;
+
+/// Argon2id iteration count
+#[cfg(not(test))]
+const ARGON2_T_COST: u32 = Params::DEFAULT_T_COST;
+#[cfg(test)]
+const ARGON2_T_COST: u32 = Params::MIN_T_COST;
+
+/// Argon2id parallelism
+const ARGON2_P_COST: u32 = Params::DEFAULT_P_COST;
+
+/// Argon2id parameters and salt for a wallet file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    pub m_cost: u32,
+    /// Number of iterations
+    pub t_cost: u32,
+    /// Degree of parallelism
+    pub p_cost: u32,
+    /// Random salt
+    #[serde(with = "hex")]
+    pub salt: Vec<u8>,
+}
+
+impl KdfParams {
+    /// Fresh parameters with a random salt
+    fn generate() -> Self {
+        let mut salt = vec![0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self {
+            m_cost: ARGON2_M_COST,
+            t_cost: ARGON2_T_COST,
+            p_cost: ARGON2_P_COST,
+            salt,
+        }
+    }
+
+    /// Derive the wallet master key from a password
+    fn derive_key(&self, password: &str) -> Result<Zeroizing<[u8; 32]>> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| ClientError::StateError(format!("Invalid KDF parameters: {}", e)))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &self.salt, key.as_mut())
+            .map_err(|e| ClientError::StateError(format!("Key derivation failed: {}", e)))?;
+        Ok(key)
+    }
+}
+
+/// The on-disk form of an encrypted wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedWallet {
+    /// Format version
+    pub version: u32,
+    /// Password KDF parameters
+    pub kdf: KdfParams,
+    /// AEAD nonce
+    #[serde(with = "hex")]
+    pub nonce: Vec<u8>,
+    /// Encrypted state with Poly1305 tag
+    #[serde(with = "hex")]
+    pub ciphertext: Vec<u8>,
+}
+
+/// Key that seals and opens a wallet file, derived once per unlock
+pub struct WalletCipher {
+    kdf: KdfParams,
+    key: Zeroizing<[u8; 32]>,
+    index_key: Zeroizing<[u8; 32]>,
+}
+
+impl fmt::Debug for WalletCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WalletCipher").field("kdf", &self.kdf).finish_non_exhaustive()
+    }
+}
+
+impl WalletCipher {
+    /// Create a cipher for a new password with a fresh salt
+    pub fn new(password: &str) -> Result<Self> {
+        Self::derive(KdfParams::generate(), password)
+    }
+
+    /// Re-derive the cipher for stored KDF parameters
+    ///
+    /// A wrong password is only detected when the first record fails to decrypt.
+    pub fn derive(kdf: KdfParams, password: &str) -> Result<Self> {
+        let master_key = kdf.derive_key(password)?;
+        let hkdf = Hkdf::<Sha256>::new(None, master_key.as_ref());
+        let expand = |info: &[u8]| {
+            let mut key = Zeroizing::new([0u8; 32]);
+            hkdf.expand(info, key.as_mut())
+                .expect("32 bytes is a valid HKDF-SHA256 output length");
+            key
+        };
+        let key = expand(ENCRYPTION_KEY_INFO);
+        let index_key = expand(INDEX_KEY_INFO);
+        Ok(Self {
+            kdf,
+            key,
+            index_key,
+        })
+    }
+
+    /// KDF parameters this cipher was derived with
+    pub fn kdf(&self) -> &KdfParams {
+        &self.kdf
+    }
+
+    /// Unlock an encrypted wallet, returning the cipher and the decrypted state
+    pub fn unlock(wallet: &EncryptedWallet, password: &str) -> Result<(Self, Zeroizing<Vec<u8>>)> {
+        if wallet.version != VAULT_VERSION {
+            return Err(ClientError::StateError(format!(
+                "Unsupported wallet format version: {}",
+                wallet.version
+            )));
+        }
+        let nonce: [u8; 24] = wallet
+            .nonce
+            .as_slice()
+            .try_into()
+            .map_err(|_| ClientError::StateError("Invalid wallet nonce".to_string()))?;
+
+        let cipher = Self::derive(wallet.kdf.clone(), password)?;
+        let payload = Payload {
+            msg: &wallet.ciphertext,
+            aad: VAULT_AAD,
+        };
+        let plaintext = cipher
+            .aead()
+            .decrypt(&XNonce::from(nonce), payload)
+            .map_err(|_| ClientError::InvalidPassword)?;
+        Ok((cipher, Zeroizing::new(plaintext)))
+    }
+
+    /// Encrypt serialized state under a fresh nonce
+    pub fn seal(&self, plaintext: &[u8]) -> Result<EncryptedWallet> {
+        let mut nonce = [0u8; 24];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: plaintext,
+            aad: VAULT_AAD,
+        };
+        let ciphertext = self
+            .aead()
+            .encrypt(&XNonce::from(nonce), payload)
+            .map_err(|_| ClientError::StateError("Wallet encryption failed".to_string()))?;
+
+        Ok(EncryptedWallet {
+            version: VAULT_VERSION,
+            kdf: self.kdf.clone(),
+            nonce: nonce.to_vec(),
+            ciphertext,
+        })
+    }
+
+    /// Encrypt a single record, returning the nonce followed by the ciphertext
+    ///
+    /// `aad` binds the record to where it is stored, so it cannot be moved.
+    pub fn encrypt(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        let ciphertext = self
+            .aead()
+            .encrypt(&XNonce::from(nonce), payload)
+            .map_err(|_| ClientError::StateError("Wallet encryption failed".to_string()))?;
+
+        let mut output = nonce.to_vec();
+        output.extend_from_slice(&ciphertext);
+        Ok(output)
+    }
+
+    /// Decrypt a record produced by [`WalletCipher::encrypt`]
+    pub fn decrypt(&self, aad: &[u8], data: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        if data.len() < NONCE_LEN {
+            return Err(ClientError::InvalidPassword);
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().expect("split at nonce length");
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        let plaintext = self
+            .aead()
+            .decrypt(&XNonce::from(nonce), payload)
+            .map_err(|_| ClientError::InvalidPassword)?;
+        Ok(Zeroizing::new(plaintext))
+    }
+
+    /// Keyed hash of a value, used to index records without storing the value
+    pub fn index_tag(&self, value: &[u8]) -> [u8; 32] {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.index_key.as_ref())
+            .expect("HMAC accepts any key length");
+        mac.update(value);
+        mac.finalize().into_bytes().into()
+    }
+
+    fn aead(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new_from_slice(self.key.as_ref()).expect("wallet key is 32 bytes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_unlock_roundtrip() {
+        let cipher = WalletCipher::new("correct horse").unwrap();
+        let wallet = cipher.seal(b"{\"accounts\": {}}").unwrap();
+
+        let (_, plaintext) = WalletCipher::unlock(&wallet, "correct horse").unwrap();
+        assert_eq!(plaintext.as_slice(), b"{\"accounts\": {}}");
+    }
+
+    #[test]
+    fn test_wrong_password_is_rejected() {
+        let wallet = WalletCipher::new("correct horse").unwrap().seal(b"state").unwrap();
+        assert!(matches!(
+            WalletCipher::unlock(&wallet, "battery staple"),
+            Err(ClientError::InvalidPassword)
+        ));
+    }
+
+    #[test]
+    fn test_records_are_bound_to_aad() {
+        let cipher = WalletCipher::new("pw").unwrap();
+        let record = cipher.encrypt(b"row 1", b"note").unwrap();
+
+        assert_eq!(cipher.decrypt(b"row 1", &record).unwrap().as_slice(), b"note");
+        assert!(matches!(
+            cipher.decrypt(b"row 2", &record),
+            Err(ClientError::InvalidPassword)
+        ));
+    }
+
+    #[test]
+    fn test_index_tags_depend_on_password() {
+        let cipher = WalletCipher::new("pw").unwrap();
+        let same = WalletCipher::derive(cipher.kdf().clone(), "pw").unwrap();
+        let other = WalletCipher::derive(cipher.kdf().clone(), "other").unwrap();
+
+        assert_eq!(cipher.index_tag(b"address"), same.index_tag(b"address"));
+        assert_ne!(cipher.index_tag(b"address"), other.index_tag(b"address"));
+        assert_ne!(cipher.index_tag(b"address"), cipher.index_tag(b"commitment"));
+    }
+
+    #[test]
+    fn test_keys_are_expanded_from_the_master_key() {
+        let cipher = WalletCipher::new("pw").unwrap();
+        let master_key = cipher.kdf().derive_key("pw").unwrap();
+        assert_ne!(cipher.key, master_key);
+        assert_ne!(cipher.index_key, master_key);
+        assert_ne!(cipher.key, cipher.index_key);
+    }
+
+    #[test]
+    fn test_each_seal_uses_fresh_nonce() {
+        let cipher = WalletCipher::new("pw").unwrap();
+        let first = cipher.seal(b"state").unwrap();
+        let second = cipher.seal(b"state").unwrap();
+        assert_ne!(first.nonce, second.nonce);
+        assert_ne!(first.ciphertext, second.ciphertext);
+    }
+}
@@ -31,7 +31,8 @@ contract PrivateToken {
         bytes32 indexed nullifier, 
         bytes32 senderOutput, 
         bytes32 recipientOutput,
-        uint256 timestamp
+        uint256 timestamp,
+        bytes encryptedNote
     );
     event PrivateMint(bytes32 indexed commitment, uint256 requestId, uint256 timestamp);
     event VerifierUpdated(string verifierType, address newVerifier);
@@ -99,8 +100,15 @@ contract PrivateToken {
      * @param proof ZK proof of valid transfer
      * @param publicInputs [input_commitment, output_commitment_sender, 
      *                      output_commitment_recipient, nullifier, new_nonce]
+     * @param encryptedNote Recipient output note encrypted to the recipient's key,
+     *                      followed by the sender's change note encrypted to the
+     *                      sender's outgoing key, emitted so both can discover it
      */
-    function transfer(bytes calldata proof, bytes32[] calldata publicInputs) external {
+    function transfer(
+        bytes calldata proof,
+        bytes32[] calldata publicInputs,
+        bytes calldata encryptedNote
+    ) external {
         require(publicInputs.length == 5, "Invalid public inputs");
         
         bytes32 inputCommitment = publicInputs[0];
@@ -130,7 +138,13 @@ contract PrivateToken {
         emit NullifierUsed(nullifier);
         emit CommitmentAdded(outputCommitmentSender, commitmentCount - 1);
         emit CommitmentAdded(outputCommitmentRecipient, commitmentCount);
-        emit PrivateTransfer(nullifier, outputCommitmentSender, outputCommitmentRecipient, block.timestamp);
+        emit PrivateTransfer(
+            nullifier,
+            outputCommitmentSender,
+            outputCommitmentRecipient,
+            block.timestamp,
+            encryptedNote
+        );
     }
     
     /**
@@ -18,6 +18,7 @@ contract PrivateTokenTest is Test {
     bytes32 public commitment2 = keccak256("commitment2");
     bytes32 public commitment3 = keccak256("commitment3");
     bytes32 public nullifier1 = keccak256("nullifier1");
+    bytes public encryptedNote = hex"abcdef";
     
     function setUp() public {
         transferVerifier = new UltraVerifier();
@@ -67,7 +68,7 @@ contract PrivateTokenTest is Test {
         transferInputs[3] = nullifier1;  // nullifier
         transferInputs[4] = bytes32(uint256(2)); // new nonce
         
-        token.transfer(transferProof, transferInputs);
+        token.transfer(transferProof, transferInputs, encryptedNote);
         
         assertTrue(token.hasCommitment(commitment2));
         assertTrue(token.hasCommitment(commitment3));
@@ -75,6 +76,24 @@ contract PrivateTokenTest is Test {
         assertEq(token.getCommitmentCount(), 3);
     }
     
+    function testTransferEmitsEncryptedNote() public {
+        bytes32[] memory mintInputs = new bytes32[](2);
+        mintInputs[0] = commitment1;
+        mintInputs[1] = bytes32(uint256(1));
+        token.mint(hex"1234", mintInputs);
+        
+        bytes32[] memory transferInputs = new bytes32[](5);
+        transferInputs[0] = commitment1;
+        transferInputs[1] = commitment2;
+        transferInputs[2] = commitment3;
+        transferInputs[3] = nullifier1;
+        transferInputs[4] = bytes32(uint256(2));
+        
+        vm.expectEmit(true, false, false, true);
+        emit PrivateToken.PrivateTransfer(nullifier1, commitment2, commitment3, block.timestamp, encryptedNote);
+        token.transfer(hex"5678", transferInputs, encryptedNote);
+    }
+    
     function testTransferDoubleSpendFails() public {
         // Mint
         bytes memory mintProof = hex"1234";
@@ -91,7 +110,7 @@ contract PrivateTokenTest is Test {
         transferInputs[2] = commitment3;
         transferInputs[3] = nullifier1;
         transferInputs[4] = bytes32(uint256(2));
-        token.transfer(transferProof, transferInputs);
+        token.transfer(transferProof, transferInputs, encryptedNote);
         
         // Try to use same nullifier again (double spend)
         bytes32 commitment4 = keccak256("commitment4");
@@ -103,7 +122,7 @@ contract PrivateTokenTest is Test {
         transferInputs[4] = bytes32(uint256(3));
         
         vm.expectRevert(PrivateToken.NullifierAlreadyUsed.selector);
-        token.transfer(transferProof, transferInputs);
+        token.transfer(transferProof, transferInputs, encryptedNote);
     }
     
     function testTransferNonExistentCommitmentFails() public {
@@ -116,7 +135,7 @@ contract PrivateTokenTest is Test {
         transferInputs[4] = bytes32(uint256(2));
         
         vm.expectRevert(PrivateToken.CommitmentNotFound.selector);
-        token.transfer(transferProof, transferInputs);
+        token.transfer(transferProof, transferInputs, encryptedNote);
     }
     
     function testSetVerifier() public {